@@ -37,7 +37,7 @@ impl OpeningBook {
             offset += bytes as u64;
             line_count += 1;
 
-            if line_count % BOOK_CHECKPOINT_INTERVAL == 0 {
+            if line_count.is_multiple_of(BOOK_CHECKPOINT_INTERVAL) {
                 checkpoints.push(offset);
             }
         }
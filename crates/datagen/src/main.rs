@@ -16,8 +16,8 @@ use monty::{
 
 use std::{
     env::Args,
-    fs::File,
-    io::{BufWriter, Write},
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Seek, Write},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
@@ -62,84 +62,140 @@ pub fn to_slice_with_lifetime<T, U>(slice: &[T]) -> &[U] {
 
 pub struct Destination {
     writer: BufWriter<File>,
-    reusable_buffer: Vec<u8>,
     games: usize,
     limit: usize,
     searches: usize,
     iters: usize,
     results: [usize; 3],
+    dist_positions: usize,
+    dist_entries: usize,
 }
 
-impl Destination {
-    pub fn push(
-        &mut self,
-        game: &MontyValueFormat,
-        stop: &AtomicBool,
-        searches: usize,
-        iters: usize,
-    ) {
-        if stop.load(Ordering::Relaxed) {
-            return;
-        }
+/// Stats for one game held in a [`GameBatch`].
+struct BatchedGame {
+    len: usize,
+    result: usize,
+    searches: usize,
+    iters: usize,
+    dist_positions: usize,
+    dist_entries: usize,
+}
 
-        let result = (2.0 * game.result) as usize;
-        self.results[result] += 1;
-        self.games += 1;
+/// Games finished by a single thread that have not yet been written out,
+/// so the [`Destination`] lock is taken once per batch rather than per game.
+#[derive(Default)]
+pub struct GameBatch {
+    bytes: Vec<u8>,
+    games: Vec<BatchedGame>,
+    reusable_buffer: Vec<u8>,
+}
 
-        // accumulate stats so report() can print the average iters
-        self.searches += searches;
-        self.iters += iters;
+impl GameBatch {
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
 
-        game.serialise_into(&mut self.writer).unwrap();
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
 
-        if self.games >= self.limit {
-            stop.store(true, Ordering::Relaxed);
-            return;
-        }
+    pub fn push(&mut self, game: &MontyValueFormat, searches: usize, iters: usize) {
+        let before = self.bytes.len();
+        game.serialise_into(&mut self.bytes).unwrap();
+
+        self.games.push(BatchedGame {
+            len: self.bytes.len() - before,
+            result: (2.0 * game.result) as usize,
+            searches,
+            iters,
+            dist_positions: 0,
+            dist_entries: 0,
+        });
+    }
 
-        if self.games.is_multiple_of(64) {
-            self.report();
+    pub fn push_policy(&mut self, game: &MontyFormat, searches: usize, iters: usize) {
+        let mut dist_positions = 0;
+        let mut dist_entries = 0;
+
+        for dist in game
+            .moves
+            .iter()
+            .filter_map(|data| data.visit_distribution.as_ref())
+        {
+            dist_positions += 1;
+            dist_entries += dist.iter().filter(|(_, visits)| *visits > 0).count();
         }
+
+        game.serialise_into_buffer(&mut self.reusable_buffer)
+            .unwrap();
+        self.bytes.extend_from_slice(&self.reusable_buffer);
+
+        self.games.push(BatchedGame {
+            len: self.reusable_buffer.len(),
+            result: (game.result * 2.0) as usize,
+            searches,
+            iters,
+            dist_positions,
+            dist_entries,
+        });
+
+        self.reusable_buffer.clear();
     }
 
-    pub fn push_policy(
-        &mut self,
-        game: &MontyFormat,
-        stop: &AtomicBool,
-        searches: usize,
-        iters: usize,
-    ) {
+    fn clear(&mut self) {
+        self.bytes.clear();
+        self.games.clear();
+    }
+}
+
+impl Destination {
+    /// Writes out as many games from `batch` as fit under the game limit,
+    /// in a single write, then empties the batch.
+    pub fn push_batch(&mut self, batch: &mut GameBatch, stop: &AtomicBool) {
         if stop.load(Ordering::Relaxed) {
+            batch.clear();
             return;
         }
 
-        let result = (game.result * 2.0) as usize;
-        self.results[result] += 1;
-        self.games += 1;
+        let mut written = 0;
 
-        self.searches += searches;
-        self.iters += iters;
+        for game in &batch.games {
+            if self.games >= self.limit {
+                break;
+            }
 
-        game.serialise_into_buffer(&mut self.reusable_buffer)
-            .unwrap();
-        self.writer.write_all(&self.reusable_buffer).unwrap();
-        self.reusable_buffer.clear();
+            self.results[game.result] += 1;
+            self.games += 1;
 
-        if self.games >= self.limit {
-            stop.store(true, Ordering::Relaxed);
-            return;
+            // accumulate stats so report() can print the average iters
+            self.searches += game.searches;
+            self.iters += game.iters;
+            self.dist_positions += game.dist_positions;
+            self.dist_entries += game.dist_entries;
+
+            written += game.len;
+
+            if self.games.is_multiple_of(64) {
+                self.report();
+            }
         }
 
-        if self.games.is_multiple_of(64) {
-            self.report();
+        self.writer.write_all(&batch.bytes[..written]).unwrap();
+        batch.clear();
+
+        if self.games >= self.limit {
+            stop.store(true, Ordering::Relaxed);
         }
     }
 
     pub fn report(&self) {
-        if self.searches != 0 {
-            let average_iters = self.iters / self.searches;
+        if let Some(average_iters) = self.iters.checked_div(self.searches) {
             println!("average iters {average_iters}");
         }
+        if self.dist_positions != 0 {
+            let average_size = self.dist_entries as f64 / self.dist_positions as f64;
+            println!("average distribution size {average_size:.2}");
+        }
         println!(
             "finished games {} losses {} draws {} wins {}",
             self.games, self.results[0], self.results[1], self.results[2],
@@ -159,32 +215,62 @@ pub fn run_datagen(
     let stop_base = AtomicBool::new(false);
     let stop = &stop_base;
 
-    let vout = File::create(opts.out_path.as_str()).unwrap();
+    let (vout, existing) = if opts.resume {
+        open_for_resume(opts.out_path.as_str(), opts.policy_data).unwrap()
+    } else {
+        (File::create(opts.out_path.as_str()).unwrap(), 0)
+    };
+
+    if opts.resume {
+        println!("resuming from {existing} games");
+
+        if existing >= opts.games {
+            return;
+        }
+    }
+
     let vout = BufWriter::new(vout);
     let dest = Destination {
         writer: vout,
-        reusable_buffer: Vec::new(),
         games: 0,
         searches: 0,
         iters: 0,
-        limit: opts.games,
+        limit: opts.games - existing,
         results: [0; 3],
+        dist_positions: 0,
+        dist_entries: 0,
     };
 
     let dest_mutex = Arc::new(Mutex::new(dest));
 
     let book = opts
         .book
+        .clone()
         .map(|path| OpeningBook::load(path).expect("failed to load opening book"));
 
+    // each thread derives its own stream from the master seed, so a run
+    // can be repeated by passing the printed seed back in
+    let seed = opts.seed.unwrap_or_else(Rand::time_seed);
+    println!("seed {seed}");
+
+    let opts = &opts;
+
     std::thread::scope(|s| {
-        for _ in 0..opts.threads {
+        for thread_id in 0..opts.threads {
             let params = params.clone();
             std::thread::sleep(Duration::from_millis(10));
             let this_book = book.clone();
             let this_dest = dest_mutex.clone();
             s.spawn(move || {
-                let mut thread = DatagenThread::new(params.clone(), stop, this_book, this_dest);
+                let thread_seed = seed ^ thread_id as u64;
+                let mut thread = DatagenThread::new(
+                    params.clone(),
+                    stop,
+                    this_book,
+                    this_dest,
+                    opts,
+                    thread_seed,
+                );
                 thread.run(opts.policy_data, policy, value);
             });
         }
@@ -195,6 +281,39 @@ pub fn run_datagen(
     dest.report();
 }
 
+/// Opens an existing output file to append to, returning it along with the
+/// number of complete games it holds. Any partially written game left at the
+/// end by an interrupted run is cut off first.
+fn open_for_resume(path: &str, policy_data: bool) -> std::io::Result<(File, usize)> {
+    let Ok(file) = File::open(path) else {
+        return Ok((File::create(path)?, 0));
+    };
+
+    let mut reader = BufReader::new(file);
+    let mut games = 0;
+    let mut valid_len = 0;
+
+    loop {
+        let read = if policy_data {
+            MontyFormat::deserialise_from(&mut reader).map(|_| ())
+        } else {
+            MontyValueFormat::deserialise_from(&mut reader, Vec::new()).map(|_| ())
+        };
+
+        if read.is_err() {
+            break;
+        }
+
+        games += 1;
+        valid_len = reader.stream_position()?;
+    }
+
+    let file = OpenOptions::new().append(true).open(path)?;
+    file.set_len(valid_len)?;
+
+    Ok((file, games))
+}
+
 #[derive(Debug, Default)]
 pub struct RunOptions {
     games: usize,
@@ -203,6 +322,13 @@ pub struct RunOptions {
     policy_data: bool,
     nodes: usize,
     out_path: String,
+    noise: (f32, f32),
+    draw_halfmove: Option<u8>,
+    policy_top_k: Option<usize>,
+    random_plies: usize,
+    random_policy_temp: f32,
+    seed: Option<u64>,
+    resume: bool,
 }
 
 pub fn parse_args(args: Args) -> Option<RunOptions> {
@@ -214,15 +340,28 @@ pub fn parse_args(args: Args) -> Option<RunOptions> {
         opts.policy_data = true;
     }
 
+    // (alpha, epsilon) of the dirichlet noise mixed into the first root
+    let epsilon = if cfg!(feature = "policy") { 0.05 } else { 0.25 };
+    opts.noise = (0.03, epsilon);
+    opts.random_policy_temp = 1.0;
+
     for arg in args {
         match arg.as_str() {
             "bench" => return None,
             "--policy-data" => opts.policy_data = true,
+            "--resume" => opts.resume = true,
             "-t" | "--threads" => mode = 1,
             "-b" | "--book" => mode = 2,
             "-n" | "--nodes" => mode = 3,
             "-o" | "--output" => mode = 4,
             "-g" | "--games" => mode = 5,
+            "--dirichlet-alpha" => mode = 6,
+            "--dirichlet-epsilon" => mode = 7,
+            "--draw-halfmove" => mode = 8,
+            "--policy-top-k" => mode = 9,
+            "--random-plies" => mode = 10,
+            "--random-policy-temp" => mode = 11,
+            "--seed" => mode = 12,
             _ => match mode {
                 1 => {
                     opts.threads = arg.parse().expect("can't parse");
@@ -244,6 +383,34 @@ pub fn parse_args(args: Args) -> Option<RunOptions> {
                     opts.games = arg.parse().expect("can't parse");
                     mode = 0;
                 }
+                6 => {
+                    opts.noise.0 = arg.parse().expect("can't parse");
+                    mode = 0;
+                }
+                7 => {
+                    opts.noise.1 = arg.parse().expect("can't parse");
+                    mode = 0;
+                }
+                8 => {
+                    opts.draw_halfmove = Some(arg.parse().expect("can't parse"));
+                    mode = 0;
+                }
+                9 => {
+                    opts.policy_top_k = Some(arg.parse::<usize>().expect("can't parse").max(1));
+                    mode = 0;
+                }
+                10 => {
+                    opts.random_plies = arg.parse().expect("can't parse");
+                    mode = 0;
+                }
+                11 => {
+                    opts.random_policy_temp = arg.parse::<f32>().expect("can't parse").max(0.0);
+                    mode = 0;
+                }
+                12 => {
+                    opts.seed = Some(arg.parse().expect("can't parse"));
+                    mode = 0;
+                }
                 _ => println!("unrecognised argument {arg}"),
             },
         }
@@ -251,3 +418,178 @@ pub fn parse_args(args: Args) -> Option<RunOptions> {
 
     Some(opts)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("monty-datagen-{}-{name}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn destination(file: File, limit: usize) -> Destination {
+        Destination {
+            writer: BufWriter::new(file),
+            games: 0,
+            limit,
+            searches: 0,
+            iters: 0,
+            results: [0; 3],
+            dist_positions: 0,
+            dist_entries: 0,
+        }
+    }
+
+    /// A short game from the start position, always playing the first
+    /// legal move.
+    fn game(result: f32) -> MontyValueFormat {
+        let mut position = ChessState::default();
+        let mut game = MontyValueFormat {
+            startpos: position.board(),
+            castling: position.castling(),
+            result,
+            moves: Vec::new(),
+        };
+
+        for _ in 0..6 {
+            let mut first = None;
+            position.map_legal_moves(|mov| {
+                first.get_or_insert(mov);
+            });
+
+            let mov = first.unwrap();
+            game.push(position.stm(), mov, 0.5);
+            position.make_move(mov);
+        }
+
+        game
+    }
+
+    fn count_games(path: &str) -> usize {
+        let mut reader = BufReader::new(File::open(path).unwrap());
+        let mut games = 0;
+
+        while MontyValueFormat::deserialise_from(&mut reader, Vec::new()).is_ok() {
+            games += 1;
+        }
+
+        games
+    }
+
+    /// Plays `games` games on each of two threads into `dest`, flushing
+    /// every 16 games, and returns how many each thread produced.
+    fn write_from_two_threads(dest: &Arc<Mutex<Destination>>, stop: &AtomicBool) -> [usize; 2] {
+        let games = [37, 50];
+
+        std::thread::scope(|s| {
+            for (thread_id, &count) in games.iter().enumerate() {
+                let dest = dest.clone();
+                s.spawn(move || {
+                    let mut batch = GameBatch::default();
+
+                    for i in 0..count {
+                        batch.push(&game(((i + thread_id) % 3) as f32 / 2.0), 1, thread_id + 1);
+
+                        if batch.len() >= 16 {
+                            dest.lock().unwrap().push_batch(&mut batch, stop);
+                        }
+                    }
+
+                    if !batch.is_empty() {
+                        dest.lock().unwrap().push_batch(&mut batch, stop);
+                    }
+                });
+            }
+        });
+
+        games
+    }
+
+    #[test]
+    fn batched_writes_add_up_to_every_thread_game() {
+        let path = temp_path("batched");
+        let dest = Arc::new(Mutex::new(destination(File::create(&path).unwrap(), 1000)));
+        let stop = AtomicBool::new(false);
+
+        let per_thread = write_from_two_threads(&dest, &stop);
+        let total = per_thread.iter().sum::<usize>();
+
+        let mut dest = dest.lock().unwrap();
+        dest.writer.flush().unwrap();
+
+        assert_eq!(dest.games, total);
+        assert_eq!(dest.searches, total);
+        assert_eq!(dest.iters, per_thread[0] + 2 * per_thread[1]);
+        assert_eq!(dest.results.iter().sum::<usize>(), total);
+        assert_eq!(count_games(&path), total);
+        assert!(!stop.load(Ordering::Relaxed));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn batched_writes_stop_at_the_game_limit() {
+        let path = temp_path("limited");
+        let dest = Arc::new(Mutex::new(destination(File::create(&path).unwrap(), 40)));
+        let stop = AtomicBool::new(false);
+
+        write_from_two_threads(&dest, &stop);
+
+        let mut dest = dest.lock().unwrap();
+        dest.writer.flush().unwrap();
+
+        assert_eq!(dest.games, 40);
+        assert_eq!(dest.results.iter().sum::<usize>(), 40);
+        assert_eq!(count_games(&path), 40);
+        assert!(stop.load(Ordering::Relaxed));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    /// Writes `count` games to the end of `file` through a [`Destination`].
+    fn append_games(file: File, count: usize) {
+        let mut dest = destination(file, count);
+        let mut batch = GameBatch::default();
+
+        for _ in 0..count {
+            batch.push(&game(0.5), 1, 1);
+        }
+
+        dest.push_batch(&mut batch, &AtomicBool::new(false));
+        dest.writer.flush().unwrap();
+    }
+
+    #[test]
+    fn resuming_appends_to_the_complete_games() {
+        let path = temp_path("resume");
+        let _ = std::fs::remove_file(&path);
+
+        // a missing file is created
+        let (file, existing) = open_for_resume(&path, false).unwrap();
+        assert_eq!(existing, 0);
+        append_games(file, 5);
+
+        // an interrupted run leaves part of a game at the end
+        let mut partial = Vec::new();
+        game(1.0).serialise_into(&mut partial).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&partial[..partial.len() / 2]).unwrap();
+        drop(file);
+
+        let complete_len = std::fs::metadata(&path).unwrap().len() - partial.len() as u64 / 2;
+
+        let (file, existing) = open_for_resume(&path, false).unwrap();
+        assert_eq!(existing, 5);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), complete_len);
+
+        append_games(file, 3);
+        assert_eq!(count_games(&path), 8);
+
+        let (_, existing) = open_for_resume(&path, false).unwrap();
+        assert_eq!(existing, 8);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -22,12 +22,24 @@ impl Rand {
         self.0
     }
 
-    pub fn with_seed() -> Self {
-        let seed = SystemTime::now()
+    /// Seeds from `seed` mixed through splitmix64, so that nearby seeds,
+    /// such as a master seed xored with thread ids, give unrelated streams.
+    pub fn from_seed(seed: u64) -> Self {
+        Self((splitmix64(seed) as u32).max(1))
+    }
+
+    /// A master seed from the clock, for when none is given.
+    pub fn time_seed() -> u64 {
+        SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("Guaranteed increasing.")
-            .as_micros() as u32;
-
-        Self(seed)
+            .as_nanos() as u64
     }
 }
+
+pub fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
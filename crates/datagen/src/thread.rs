@@ -1,10 +1,10 @@
 use crate::{
     book::{OpeningBook, OpeningBookReader},
-    Destination, Rand,
+    Destination, GameBatch, Rand, RunOptions,
 };
 
 use monty::{
-    chess::{ChessState, GameState},
+    chess::{ChessState, GameState, Move},
     mcts::{Limits, MctsParams, Searcher},
     networks::{PolicyNetwork, ValueNetwork},
     tree::Tree,
@@ -20,25 +20,48 @@ pub struct DatagenThread<'a> {
     rng: Rand,
     params: MctsParams,
     dest: Arc<Mutex<Destination>>,
+    batch: GameBatch,
     stop: &'a AtomicBool,
     book: Option<OpeningBookReader>,
+    moves: Vec<Move>,
+    noise: (f32, f32),
+    draw_halfmove: Option<u8>,
+    policy_top_k: Option<usize>,
+    random_plies: usize,
+    random_policy_temp: f32,
 }
 
+// search scores within this distance of 0.5 count as drawish
+// for halfmove clock adjudication
+const DRAW_ADJUDICATION_MARGIN: f32 = 0.05;
+
+// completed games are held locally and written out this many at a time
+const GAME_BATCH_SIZE: usize = 16;
+
 impl<'a> DatagenThread<'a> {
     pub fn new(
         params: MctsParams,
         stop: &'a AtomicBool,
         book: Option<OpeningBook>,
         dest: Arc<Mutex<Destination>>,
+        opts: &RunOptions,
+        seed: u64,
     ) -> Self {
         let book = book.map(|book| book.reader().expect("failed to open opening book reader"));
 
         Self {
-            rng: Rand::with_seed(),
+            rng: Rand::from_seed(seed),
             params,
             dest,
+            batch: GameBatch::default(),
             stop,
             book,
+            moves: Vec::new(),
+            noise: opts.noise,
+            draw_halfmove: opts.draw_halfmove,
+            policy_top_k: opts.policy_top_k,
+            random_plies: opts.random_plies,
+            random_policy_temp: opts.random_policy_temp,
         }
     }
 
@@ -50,6 +73,67 @@ impl<'a> DatagenThread<'a> {
 
             self.run_game(policy, value, output_policy);
         }
+
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        if !self.batch.is_empty() {
+            let mut dest = self.dest.lock().unwrap();
+            dest.push_batch(&mut self.batch, self.stop);
+        }
+    }
+
+    /// Plays `random_plies` moves sampled from the policy network at
+    /// `random_policy_temp`, so openings are varied but still plausible.
+    /// A temperature of 0 always plays the highest policy move. Returns
+    /// false if the game ended before the sampled moves were played.
+    fn play_random_plies(&mut self, position: &mut ChessState, policy: &PolicyNetwork) -> bool {
+        let mut weights = Vec::new();
+
+        for _ in 0..self.random_plies {
+            if position.game_state() != GameState::Ongoing {
+                return false;
+            }
+
+            weights.clear();
+            let mut max = f32::NEG_INFINITY;
+            position.map_moves_with_policies(policy, |mov, logit| {
+                max = max.max(logit);
+                weights.push((mov, logit));
+            });
+
+            let mov = if self.random_policy_temp == 0.0 {
+                weights
+                    .iter()
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|&(mov, _)| mov)
+            } else {
+                let mut total = 0.0;
+                for (_, weight) in weights.iter_mut() {
+                    *weight = ((*weight - max) / self.random_policy_temp).exp();
+                    total += *weight;
+                }
+
+                let mut target = self.rng.rand_int() as f32 / u32::MAX as f32 * total;
+                weights
+                    .iter()
+                    .find(|&&(_, weight)| {
+                        target -= weight;
+                        target <= 0.0 && weight > 0.0
+                    })
+                    .or_else(|| weights.iter().rev().find(|&&(_, weight)| weight > 0.0))
+                    .map(|&(mov, _)| mov)
+            };
+
+            let Some(mov) = mov else {
+                return false;
+            };
+
+            position.make_move(mov);
+        }
+
+        position.game_state() == GameState::Ongoing
     }
 
     fn run_game(&mut self, policy: &PolicyNetwork, value: &ValueNetwork, output_policy: bool) {
@@ -62,10 +146,13 @@ impl<'a> DatagenThread<'a> {
             ChessState::from_fen(ChessState::STARTPOS)
         };
 
-        let mut moves = Vec::new();
-        position.map_legal_moves(|mov| moves.push(mov));
+        if !self.play_random_plies(&mut position, policy) {
+            return;
+        }
+
+        position.collect_legal_moves_into(&mut self.moves);
 
-        if moves.is_empty() {
+        if self.moves.is_empty() {
             return;
         }
 
@@ -75,6 +162,8 @@ impl<'a> DatagenThread<'a> {
             max_time: None,
             opt_time: None,
             kld_min_gain: Some(0.000005),
+            stop_score: None,
+            mate: None,
         };
 
         let mut result = 0.5;
@@ -107,8 +196,11 @@ impl<'a> DatagenThread<'a> {
             tree.set_root_position(&position);
             let searcher = Searcher::new(&tree, &self.params, policy, value, &abort);
 
+            // only the first root of each game is perturbed
+            let noise = (searches == 0 && self.noise.1 > 0.0).then_some(self.noise);
+
             let (best_move, score, iters) =
-                searcher.search(1, limits, false, 1, false, &mut 0, true, temp);
+                searcher.search(1, limits, false, 1, false, &mut 0, noise, temp);
 
             searches += 1;
             total_iters += iters;
@@ -120,29 +212,22 @@ impl<'a> DatagenThread<'a> {
 
             value_game.push(position.stm(), best_move, score);
 
-            let mut root_count = 0;
-            position.map_legal_moves(|_| root_count += 1);
+            position.collect_legal_moves_into(&mut self.moves);
+            let root_count = self.moves.len();
 
             let dist = if root_count == 0 {
                 None
             } else {
-                let mut dist = Vec::new();
-
-                let actions = tree[tree.root_node()].actions();
-
-                for action in 0..tree[tree.root_node()].num_actions() {
-                    let node = &tree[actions + action];
-                    let mov = node.parent_move();
-                    let visits = node.visits().min(u32::MAX as u64) as u32;
-                    dist.push((mov, visits));
-                }
-
+                let dist = tree.root_distribution();
                 assert_eq!(root_count, dist.len());
-
                 Some(dist)
             };
 
-            let search_data = SearchData::new(best_move, score, dist);
+            let mut search_data = SearchData::new(best_move, score, dist);
+
+            if let Some(k) = self.policy_top_k {
+                search_data.keep_top_k(k);
+            }
 
             policy_game.push(search_data);
 
@@ -150,7 +235,17 @@ impl<'a> DatagenThread<'a> {
 
             let game_state = position.game_state();
             match game_state {
-                GameState::Ongoing => {}
+                GameState::Ongoing => {
+                    // long stretches without progress in a level position
+                    // are scored as draws rather than played out
+                    if let Some(threshold) = self.draw_halfmove {
+                        if position.board().halfm() >= threshold
+                            && (score - 0.5).abs() <= DRAW_ADJUDICATION_MARGIN
+                        {
+                            break;
+                        }
+                    }
+                }
                 GameState::Draw => break,
                 GameState::Lost(_) => {
                     if position.stm() == 1 {
@@ -180,12 +275,70 @@ impl<'a> DatagenThread<'a> {
             return;
         }
 
-        let mut dest = self.dest.lock().unwrap();
-
         if output_policy {
-            dest.push_policy(&policy_game, self.stop, searches, total_iters);
+            self.batch.push_policy(&policy_game, searches, total_iters);
         } else {
-            dest.push(&value_game, self.stop, searches, total_iters);
+            self.batch.push(&value_game, searches, total_iters);
         }
+
+        if self.batch.len() >= GAME_BATCH_SIZE {
+            self.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs::File, io::BufWriter};
+
+    /// Position after the policy-sampled opening plies of the thread that
+    /// `master` and `thread_id` seed, under a uniform policy.
+    fn opening(master: u64, thread_id: u64, policy: &PolicyNetwork) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "monty-datagen-test-{}-{master}-{thread_id}",
+            std::process::id()
+        ));
+
+        let dest = Destination {
+            writer: BufWriter::new(File::create(&path).unwrap()),
+            games: 0,
+            limit: 0,
+            searches: 0,
+            iters: 0,
+            results: [0; 3],
+            dist_positions: 0,
+            dist_entries: 0,
+        };
+
+        let opts = RunOptions {
+            random_plies: 8,
+            random_policy_temp: 1.0,
+            ..RunOptions::default()
+        };
+
+        let stop = AtomicBool::new(false);
+        let dest = Arc::new(Mutex::new(dest));
+        let params = MctsParams::default();
+        let mut thread = DatagenThread::new(params, &stop, None, dest, &opts, master ^ thread_id);
+
+        let mut position = ChessState::default();
+        assert!(thread.play_random_plies(&mut position, policy));
+
+        let _ = std::fs::remove_file(path);
+        position.board().as_fen()
+    }
+
+    #[test]
+    fn master_seed_reproduces_openings_and_separates_threads() {
+        let policy: Box<PolicyNetwork> = unsafe { monty::boxed_and_zeroed() };
+
+        let first = opening(42, 0, &policy);
+        let second = opening(42, 1, &policy);
+
+        assert_ne!(first, second);
+        assert_eq!(opening(42, 0, &policy), first);
+        assert_eq!(opening(42, 1, &policy), second);
+        assert_ne!(opening(43, 0, &policy), first);
     }
 }
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    io::{self, BufReader, Error, ErrorKind},
+};
+
+use montyformat::{
+    chess::{Castling, Move, Position},
+    MontyFormat, MontyValueFormat,
+};
+
+const DEFAULT_PLIES: usize = 8;
+const DEFAULT_TOP: usize = 20;
+const DEFAULT_FLAG_PERCENT: f64 = 1.0;
+
+struct Options {
+    format_kind: String,
+    input_path: String,
+    plies: usize,
+    top: usize,
+    flag_percent: f64,
+}
+
+fn main() -> io::Result<()> {
+    let opts = parse_args()?;
+
+    let mut reader = BufReader::new(File::open(&opts.input_path)?);
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut games = 0usize;
+
+    loop {
+        let line = match opts.format_kind.as_str() {
+            "policy" => match MontyFormat::deserialise_from(&mut reader) {
+                Ok(game) => {
+                    let moves = game.moves.iter().map(|data| data.best_move);
+                    opening_line(game.startpos, &game.castling, moves, opts.plies)
+                }
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            },
+            "value" => match MontyValueFormat::deserialise_from(&mut reader, Vec::new()) {
+                Ok(game) => {
+                    let moves = game.moves.iter().map(|data| data.best_move);
+                    opening_line(game.startpos, &game.castling, moves, opts.plies)
+                }
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            },
+            _ => {
+                return Err(usage_error(
+                    "Unknown format kind. Expected 'policy' or 'value'",
+                ))
+            }
+        };
+
+        *counts.entry(line).or_insert(0) += 1;
+        games += 1;
+    }
+
+    if games == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Input binpack does not contain any games",
+        ));
+    }
+
+    // most common first, ties broken by line so the report is reproducible
+    let mut lines: Vec<(String, usize)> = counts.into_iter().collect();
+    lines.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let singletons = lines.iter().filter(|(_, count)| *count == 1).count();
+
+    println!("games            {games}");
+    println!("plies            {}", opts.plies);
+    println!(
+        "distinct lines   {} ({:.2}% of games)",
+        lines.len(),
+        percent(lines.len(), games)
+    );
+    println!(
+        "unique lines     {singletons} ({:.2}% of games)",
+        percent(singletons, games)
+    );
+    println!();
+
+    for (rank, (line, count)) in lines.iter().take(opts.top).enumerate() {
+        let share = percent(*count, games);
+        let flag = if share > opts.flag_percent { '!' } else { ' ' };
+        println!("{:>4} {count:>10} {share:>7.3}% {flag} {line}", rank + 1);
+    }
+
+    let flagged = lines
+        .iter()
+        .filter(|(_, count)| percent(*count, games) > opts.flag_percent)
+        .count();
+
+    if flagged > 0 {
+        println!();
+        println!(
+            "{flagged} lines exceed {:.2}% of games and are marked with '!'",
+            opts.flag_percent
+        );
+    }
+
+    Ok(())
+}
+
+/// Start position followed by the first `plies` moves of the game in UCI notation.
+fn opening_line(
+    startpos: Position,
+    castling: &Castling,
+    moves: impl Iterator<Item = Move>,
+    plies: usize,
+) -> String {
+    let mut line = startpos.as_fen();
+
+    for mov in moves.take(plies) {
+        line.push(' ');
+        line.push_str(&mov.to_uci(castling));
+    }
+
+    line
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    count as f64 / total as f64 * 100.0
+}
+
+fn parse_args() -> io::Result<Options> {
+    let mut args = env::args().skip(1);
+
+    let format_kind = args
+        .next()
+        .ok_or_else(|| usage_error("Missing format kind (policy/value)"))?;
+
+    let input_path = args
+        .next()
+        .ok_or_else(|| usage_error("Missing input binpack path"))?;
+
+    let mut opts = Options {
+        format_kind,
+        input_path,
+        plies: DEFAULT_PLIES,
+        top: DEFAULT_TOP,
+        flag_percent: DEFAULT_FLAG_PERCENT,
+    };
+
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| usage_error(&format!("Missing value for {flag}")))?;
+
+        match flag.as_str() {
+            "--plies" | "-k" => opts.plies = parse_value(&value, "plies")?,
+            "--top" | "-n" => opts.top = parse_value(&value, "top")?,
+            "--flag" | "-f" => opts.flag_percent = parse_value(&value, "flag percentage")?,
+            _ => return Err(usage_error(&format!("Unknown argument {flag}"))),
+        }
+    }
+
+    Ok(opts)
+}
+
+fn parse_value<T: std::str::FromStr>(value: &str, name: &str) -> io::Result<T> {
+    value
+        .parse::<T>()
+        .map_err(|_| usage_error(&format!("Invalid {name}: {value}")))
+}
+
+fn usage_error(message: &str) -> io::Error {
+    Error::new(ErrorKind::InvalidInput, format!(
+        "{message}. Usage: opening_report <policy|value> <input.binpack> [--plies <k>] [--top <n>] [--flag <percent>]"
+    ))
+}
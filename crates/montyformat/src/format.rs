@@ -36,6 +36,26 @@ impl SearchData {
             visit_distribution,
         }
     }
+
+    /// Zeroes the visits of every move outside the `k` most visited,
+    /// giving a sparser policy target. The distribution still lists
+    /// every legal move, as the format requires.
+    pub fn keep_top_k(&mut self, k: usize) {
+        let Some(dist) = self.visit_distribution.as_mut() else {
+            return;
+        };
+
+        if k >= dist.len() {
+            return;
+        }
+
+        let mut order: Vec<usize> = (0..dist.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(dist[i].1));
+
+        for &i in &order[k..] {
+            dist[i].1 = 0;
+        }
+    }
 }
 
 pub struct MontyFormat {
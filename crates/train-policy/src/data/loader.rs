@@ -35,6 +35,12 @@ impl MontyDataLoader {
             threads: loader_threads,
         }
     }
+
+    /// See [`DataReader::with_shuffle`].
+    pub fn with_shuffle(mut self, positions: usize, seed: u64) -> Self {
+        self.reader = self.reader.with_shuffle(positions, seed);
+        self
+    }
 }
 
 impl DataLoader for MontyDataLoader {
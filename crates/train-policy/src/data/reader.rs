@@ -25,6 +25,8 @@ pub struct DataReader {
     file_path: String,
     buffer_size: usize,
     threads: usize,
+    reservoir_size: usize,
+    seed: u64,
 }
 
 impl DataReader {
@@ -33,8 +35,19 @@ impl DataReader {
             file_path: path.to_string(),
             buffer_size: buffer_size_mb * 1024 * 1024 / std::mem::size_of::<DecompressedData>() / 2,
             threads,
+            reservoir_size: 0,
+            seed: 0,
         }
     }
+
+    /// Streams positions through a [`ShuffleBuffer`] of `positions` entries
+    /// before they reach the main shuffle, so that a superbatch drawn from a
+    /// small file isn't made of runs from the same game.
+    pub fn with_shuffle(mut self, positions: usize, seed: u64) -> Self {
+        self.reservoir_size = positions;
+        self.seed = seed;
+        self
+    }
 }
 
 impl DataReader {
@@ -43,6 +56,8 @@ impl DataReader {
         let buffer_size = self.buffer_size;
         let threads = self.threads;
         let games_per_thread = 2048;
+        let reservoir_size = self.reservoir_size;
+        let seed = self.seed;
 
         let (game_sender, game_receiver) = mpsc::sync_channel::<Vec<u8>>(32);
 
@@ -98,6 +113,35 @@ impl DataReader {
             }
         });
 
+        let mini_receiver = if reservoir_size == 0 {
+            mini_receiver
+        } else {
+            let (reservoir_sender, reservoir_receiver) =
+                mpsc::sync_channel::<Vec<DecompressedData>>(threads);
+
+            std::thread::spawn(move || {
+                let mut reservoir = ShuffleBuffer::new(reservoir_size, seed);
+
+                while let Ok(buffer) = mini_receiver.recv() {
+                    let out: Vec<_> = buffer
+                        .into_iter()
+                        .filter_map(|point| reservoir.push(point))
+                        .collect();
+
+                    if !out.is_empty() && reservoir_sender.send(out).is_err() {
+                        return;
+                    }
+                }
+
+                let rest = reservoir.drain();
+                if !rest.is_empty() {
+                    let _ = reservoir_sender.send(rest);
+                }
+            });
+
+            reservoir_receiver
+        };
+
         let (buffer_sender, buffer_receiver) = mpsc::sync_channel::<Vec<DecompressedData>>(0);
 
         std::thread::spawn(move || {
@@ -154,6 +198,52 @@ fn shuffle(data: &mut [DecompressedData]) {
     }
 }
 
+/// Reservoir-style shuffle over a stream: once full, each new sample takes
+/// the place of a random buffered one, which is emitted instead. With a
+/// capacity of zero, samples pass straight through.
+pub struct ShuffleBuffer<T> {
+    buffer: Vec<T>,
+    capacity: usize,
+    rng: Rand,
+}
+
+impl<T> ShuffleBuffer<T> {
+    pub fn new(capacity: usize, seed: u64) -> Self {
+        Self {
+            buffer: Vec::with_capacity(capacity),
+            capacity,
+            rng: Rand::new(seed),
+        }
+    }
+
+    /// Adds `sample`, returning the sample it displaced, if any.
+    pub fn push(&mut self, sample: T) -> Option<T> {
+        if self.buffer.len() < self.capacity {
+            self.buffer.push(sample);
+            return None;
+        }
+
+        if self.capacity == 0 {
+            return Some(sample);
+        }
+
+        let idx = self.rng.rng() as usize % self.capacity;
+        Some(std::mem::replace(&mut self.buffer[idx], sample))
+    }
+
+    /// Empties the buffer in a random order, for once the stream has ended.
+    pub fn drain(&mut self) -> Vec<T> {
+        let mut rest = std::mem::take(&mut self.buffer);
+
+        for i in (0..rest.len()).rev() {
+            let idx = self.rng.rng() as usize % (i + 1);
+            rest.swap(idx, i);
+        }
+
+        rest
+    }
+}
+
 macro_rules! read_into_primitive {
     ($reader:expr, $t:ty) => {{
         let mut buf = [0u8; std::mem::size_of::<$t>()];
@@ -268,6 +358,11 @@ impl Rand {
         Self(seed)
     }
 
+    /// Xorshift is stuck at zero, so a zero seed is treated as one.
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
     pub fn rng(&mut self) -> u64 {
         self.0 ^= self.0 << 13;
         self.0 ^= self.0 >> 7;
@@ -275,3 +370,36 @@ impl Rand {
         self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(capacity: usize, seed: u64, samples: usize) -> Vec<usize> {
+        let mut reservoir = ShuffleBuffer::new(capacity, seed);
+        let mut out: Vec<_> = (0..samples).filter_map(|x| reservoir.push(x)).collect();
+        out.extend(reservoir.drain());
+        out
+    }
+
+    #[test]
+    fn same_seed_gives_same_order() {
+        assert_eq!(run(64, 7, 1000), run(64, 7, 1000));
+        assert_ne!(run(64, 7, 1000), run(64, 8, 1000));
+    }
+
+    #[test]
+    fn every_sample_comes_out_exactly_once() {
+        // fewer samples than the capacity, as many, and many more
+        for samples in [10, 64, 1000] {
+            let mut out = run(64, 3, samples);
+            assert_eq!(out.len(), samples);
+
+            out.sort_unstable();
+            assert!(out.into_iter().eq(0..samples));
+        }
+
+        assert!(run(0, 3, 100).into_iter().eq(0..100));
+        assert_ne!(run(64, 3, 1000), (0..1000).collect::<Vec<_>>());
+    }
+}
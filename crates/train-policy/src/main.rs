@@ -18,12 +18,16 @@ use data::MontyDataLoader;
 
 fn main() {
     let hl = 16384;
+    // positions held in the reservoir shuffle (0 to disable) and its seed
+    let shuffle_positions = 0;
+    let shuffle_seed = 0;
     let dataloader = MontyDataLoader::new(
         "/home/privateclient/monty_value_training/interleaved.binpack",
         96000,
         4,
         8,
-    );
+    )
+    .with_shuffle(shuffle_positions, shuffle_seed);
 
     let device = CudaDevice::new(0).unwrap();
 
@@ -44,10 +48,14 @@ fn main() {
     };
 
     let save_rate = 40;
+    // checkpoints averaged into swa-quantised.bin (0 to disable)
+    let swa_checkpoints = 4;
     let end_superbatch = 800;
     let initial_lr = 0.001;
     let final_lr = 0.00001;
 
+    let mut swa = model::WeightAverage::new(swa_checkpoints);
+
     let steps = TrainingSteps {
         batch_size: 16384,
         batches_per_superbatch: 6104,
@@ -84,6 +92,12 @@ fn main() {
                         &format!("{dir}/quantised.bin"),
                     )
                     .unwrap();
+
+                    if swa_checkpoints > 0 {
+                        swa.push(&trainer.optimiser.graph);
+                        swa.save_quantised(&format!("{dir}/swa-quantised.bin"))
+                            .unwrap();
+                    }
                 }
             },
         )
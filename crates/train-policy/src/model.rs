@@ -77,17 +77,26 @@ pub fn eval(graph: &mut Graph<CudaDevice>, node: GraphNodeId, fen: &str) {
     }
 }
 
+const SAVED_WEIGHTS: [&str; 4] = ["l0w", "l0b", "l1w", "l1b"];
+
 pub fn save_quantised(graph: &Graph<CudaDevice>, path: &str) -> std::io::Result<()> {
+    let weights: Vec<Vec<f32>> = SAVED_WEIGHTS
+        .iter()
+        .map(|id| graph.get_weights(id).get_dense_vals().unwrap())
+        .collect();
+
+    write_quantised(&weights, path)
+}
+
+fn write_quantised(weights: &[Vec<f32>], path: &str) -> std::io::Result<()> {
     use std::io::Write;
 
     let mut file = std::fs::File::create(path).unwrap();
 
     let mut quant = Vec::new();
 
-    for id in ["l0w", "l0b", "l1w", "l1b"] {
-        let vals = graph.get_weights(id).get_dense_vals().unwrap();
-
-        for x in vals {
+    for vals in weights {
+        for &x in vals {
             let q = (x * 128.0).round() as i8;
             assert_eq!((x * 128.0).round(), f32::from(q));
             quant.extend_from_slice(&q.to_le_bytes());
@@ -96,3 +105,96 @@ pub fn save_quantised(graph: &Graph<CudaDevice>, path: &str) -> std::io::Result<
 
     file.write_all(&quant)
 }
+
+/// Average of the saved weights over the most recent `capacity` checkpoints
+/// (stochastic weight averaging).
+pub struct WeightAverage {
+    capacity: usize,
+    snapshots: std::collections::VecDeque<Vec<Vec<f32>>>,
+}
+
+impl WeightAverage {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            snapshots: std::collections::VecDeque::new(),
+        }
+    }
+
+    /// Adds the current weights, dropping the oldest checkpoint once full.
+    pub fn push(&mut self, graph: &Graph<CudaDevice>) {
+        let weights = SAVED_WEIGHTS
+            .iter()
+            .map(|id| graph.get_weights(id).get_dense_vals().unwrap())
+            .collect();
+
+        self.push_weights(weights);
+    }
+
+    fn push_weights(&mut self, weights: Vec<Vec<f32>>) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+
+        self.snapshots.push_back(weights);
+    }
+
+    /// The mean is recomputed from the held checkpoints in f64 each time,
+    /// rather than kept as a running sum, so no error builds up however
+    /// many checkpoints pass through.
+    pub fn average(&self) -> Vec<Vec<f32>> {
+        let Some(first) = self.snapshots.front() else {
+            return Vec::new();
+        };
+
+        let count = self.snapshots.len() as f64;
+
+        (0..first.len())
+            .map(|tensor| {
+                let mut sums = vec![0.0f64; first[tensor].len()];
+
+                for snapshot in &self.snapshots {
+                    for (sum, &x) in sums.iter_mut().zip(&snapshot[tensor]) {
+                        *sum += f64::from(x);
+                    }
+                }
+
+                sums.iter().map(|&sum| (sum / count) as f32).collect()
+            })
+            .collect()
+    }
+
+    pub fn save_quantised(&self, path: &str) -> std::io::Result<()> {
+        write_quantised(&self.average(), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averaging_two_weight_sets_gives_their_midpoint() {
+        let mut swa = WeightAverage::new(2);
+        swa.push_weights(vec![vec![1.0, -2.0, 0.5], vec![10.0]]);
+        swa.push_weights(vec![vec![3.0, 2.0, -0.5], vec![-4.0]]);
+
+        assert_eq!(swa.average(), vec![vec![2.0, 0.0, 0.0], vec![3.0]]);
+
+        // a third set pushes the first out
+        swa.push_weights(vec![vec![5.0, 4.0, 1.5], vec![0.0]]);
+        assert_eq!(swa.average(), vec![vec![4.0, 3.0, 0.5], vec![-2.0]]);
+    }
+
+    #[test]
+    fn averaging_many_checkpoints_stays_exact() {
+        let mut swa = WeightAverage::new(10_000);
+
+        for i in 0..10_000 {
+            let x = if i % 2 == 0 { 0.1 } else { 0.3 };
+            swa.push_weights(vec![vec![x]]);
+        }
+
+        assert_eq!(swa.average(), vec![vec![0.2]]);
+    }
+}
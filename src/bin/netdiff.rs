@@ -0,0 +1,124 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, Error, ErrorKind},
+};
+
+use memmap2::Mmap;
+use monty::{
+    networks::{PolicyNetwork, ValueNetwork, WeightStats},
+    read_into_struct_unchecked,
+};
+
+/// Network files carry no header, so the architecture is inferred from
+/// the file size matching one of the structs compiled into this binary.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Value,
+    Policy,
+    Unknown,
+}
+
+impl Kind {
+    fn of_size(size: usize) -> Self {
+        if size == std::mem::size_of::<ValueNetwork>() {
+            Kind::Value
+        } else if size == std::mem::size_of::<PolicyNetwork>() {
+            Kind::Policy
+        } else {
+            Kind::Unknown
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Value => "value network",
+            Kind::Policy => "policy network",
+            Kind::Unknown => "unknown architecture",
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut args = env::args().skip(1);
+
+    let (Some(path_a), Some(path_b), None) = (args.next(), args.next(), args.next()) else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: netdiff <a.network> <b.network>",
+        ));
+    };
+
+    let map_a = map_file(&path_a)?;
+    let map_b = map_file(&path_b)?;
+
+    let kind_a = Kind::of_size(map_a.len());
+    let kind_b = Kind::of_size(map_b.len());
+
+    println!("a  {path_a} ({} bytes, {})", map_a.len(), kind_a.name());
+    println!("b  {path_b} ({} bytes, {})", map_b.len(), kind_b.name());
+    println!();
+
+    if map_a.len() != map_b.len() {
+        println!("files differ in size");
+    } else if let Some(offset) = map_a.iter().zip(map_b.iter()).position(|(a, b)| a != b) {
+        let differing = map_a
+            .iter()
+            .zip(map_b.iter())
+            .filter(|(a, b)| a != b)
+            .count();
+
+        println!("files are the same size, {differing} bytes differ (first at offset {offset:#x})");
+    } else {
+        println!("files are byte-identical");
+        return Ok(());
+    }
+
+    if kind_a != kind_b {
+        println!("architectures differ");
+    } else if kind_a == Kind::Unknown {
+        println!("architecture not recognised, skipping layer statistics");
+        return Ok(());
+    } else {
+        println!("architectures match");
+    }
+
+    for (label, path, kind) in [("a", &path_a, kind_a), ("b", &path_b, kind_b)] {
+        let stats = match kind {
+            // SAFETY: the file size was checked to be that of the network
+            Kind::Value => unsafe { read_into_struct_unchecked::<ValueNetwork>(path) }
+                .data
+                .layer_stats()
+                .to_vec(),
+            Kind::Policy => unsafe { read_into_struct_unchecked::<PolicyNetwork>(path) }
+                .data
+                .layer_stats()
+                .to_vec(),
+            Kind::Unknown => continue,
+        };
+
+        println!();
+        println!(
+            "{label}  {:<6} {:>12} {:>12} {:>12} {:>10}",
+            "layer", "min", "max", "mean", "nonfinite"
+        );
+        for (name, stats) in stats {
+            print_stats(name, &stats);
+        }
+    }
+
+    Ok(())
+}
+
+fn map_file(path: &str) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: the file is only read, and is not expected to change while mapped
+    unsafe { Mmap::map(&file) }
+}
+
+fn print_stats(name: &str, stats: &WeightStats) {
+    println!(
+        "   {name:<6} {:>12.4} {:>12.4} {:>12.4} {:>10}",
+        stats.min, stats.max, stats.mean, stats.non_finite
+    );
+}
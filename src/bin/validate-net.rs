@@ -0,0 +1,101 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, Error, ErrorKind},
+    process::ExitCode,
+};
+
+use monty::{
+    chess::{ChessState, GameState},
+    networks::ValueNetwork,
+    try_read_into_struct,
+};
+
+/// Checks a value network against reference positions, one per line as
+///
+/// `<fen> ; <min> <max>`
+///
+/// where `min` and `max` bound the expected score, from 0.0 to 1.0, for the
+/// side to move. Scores are the raw network output, `win + draw / 2`,
+/// without the contempt or material scaling that search applies. Blank
+/// lines and lines starting with `#` are skipped. Terminal positions are
+/// scored from the game state rather than the network, so mated positions
+/// should expect 0.0.
+fn main() -> io::Result<ExitCode> {
+    let mut args = env::args().skip(1);
+
+    let (Some(net_path), Some(epd_path), None) = (args.next(), args.next(), args.next()) else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: validate-net <value.network> <positions.epd>",
+        ));
+    };
+
+    // SAFETY: the value network is plain integers and floats
+    let mapped = unsafe { try_read_into_struct::<ValueNetwork>(&net_path) }?;
+    let value = mapped.get();
+
+    let reader = BufReader::new(File::open(&epd_path)?);
+
+    let mut checked = 0;
+    let mut failed = 0;
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line_no = idx + 1;
+
+        let Some((fen, min, max)) = parse_line(line) else {
+            println!("line {line_no}: could not parse \"{line}\"");
+            failed += 1;
+            continue;
+        };
+
+        let pos = ChessState::from_fen(fen);
+        let score = match pos.game_state() {
+            GameState::Ongoing => {
+                let (win, draw, _) = value.eval(&pos.board());
+                win + 0.5 * draw
+            }
+            GameState::Draw => 0.5,
+            GameState::Lost(_) => 0.0,
+            GameState::Won(_) => 1.0,
+        };
+
+        checked += 1;
+
+        if !(min..=max).contains(&score) {
+            println!("line {line_no}: {score:.3} outside [{min:.3}, {max:.3}] for {fen}");
+            failed += 1;
+        }
+    }
+
+    println!("{checked} positions checked, {failed} failed");
+
+    Ok(if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn parse_line(line: &str) -> Option<(&str, f32, f32)> {
+    let (fen, range) = line.split_once(';')?;
+    let fen = fen.trim();
+
+    // `Position::parse_fen` needs at least the board, side, rights and ep
+    if fen.split_whitespace().count() < 4 {
+        return None;
+    }
+
+    let mut range = range.split_whitespace();
+    let min = range.next()?.parse().ok()?;
+    let max = range.next()?.parse().ok()?;
+
+    (range.next().is_none() && min <= max).then_some((fen, min, max))
+}
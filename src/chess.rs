@@ -1,10 +1,20 @@
 use crate::{
     mcts::MctsParams,
-    networks::{Accumulator, PolicyNetwork, ValueNetwork, POLICY_L1},
+    networks::{
+        policy::see::{self, SEE_VALS},
+        Accumulator, EvalCache, PolicyNetwork, ValueNetwork, POLICY_L1,
+    },
 };
 
 pub use montyformat::chess::{Attacks, Castling, GameState, Move, Position};
 
+use montyformat::chess::consts::{Piece, Side};
+
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    thread,
+};
+
 #[derive(Clone, Copy, Debug)]
 pub struct EvalWdl {
     pub win: f32,
@@ -57,7 +67,10 @@ impl EvalWdl {
         (-K * (1.0 / score - 1.0).ln()) as i32
     }
 
-    pub fn apply_contempt(self, contempt: f32) -> Self {
+    /// Applies `contempt` (in centipawns) to the WDL, changing the score
+    /// by at most `max_delta` so that contempt cannot override clear
+    /// differences in evaluation.
+    pub fn apply_contempt(self, contempt: f32, max_delta: f32) -> Self {
         if contempt == 0.0 {
             return self;
         }
@@ -95,7 +108,21 @@ impl EvalWdl {
             d_new = 1.0;
         }
 
-        EvalWdl::new(w_new, d_new, l_new)
+        let shifted = EvalWdl::new(w_new, d_new, l_new);
+
+        // the score is linear in the WDL, so scaling the change
+        // componentwise caps the score delta exactly
+        let delta = shifted.score() - self.score();
+        if delta.abs() <= max_delta {
+            return shifted;
+        }
+
+        let t = max_delta / delta.abs();
+        EvalWdl::new(
+            w + t * (shifted.win - w),
+            self.draw + t * (shifted.draw - self.draw),
+            l + t * (shifted.loss - l),
+        )
     }
 }
 
@@ -151,10 +178,97 @@ impl ChessState {
         }
     }
 
+    /// As [`ChessState::from_fen`], but rejects malformed or illegal FENs
+    /// with a reason rather than panicking or producing a garbage position.
+    pub fn try_from_fen(fen: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+
+        if !(4..=6).contains(&fields.len()) {
+            return Err(format!("expected 4 to 6 fields, found {}", fields.len()));
+        }
+
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("expected 8 ranks, found {}", ranks.len()));
+        }
+
+        let mut kings = [0, 0];
+        for (idx, rank) in ranks.iter().enumerate() {
+            let mut files = 0;
+
+            for ch in rank.chars() {
+                files += match ch {
+                    '1'..='8' => ch as usize - '0' as usize,
+                    'P' | 'p' if idx == 0 || idx == 7 => {
+                        return Err("pawn on the first or last rank".to_string())
+                    }
+                    'K' | 'k' => {
+                        kings[usize::from(ch == 'k')] += 1;
+                        1
+                    }
+                    'P' | 'N' | 'B' | 'R' | 'Q' | 'p' | 'n' | 'b' | 'r' | 'q' => 1,
+                    _ => return Err(format!("invalid piece '{ch}'")),
+                };
+            }
+
+            if files != 8 {
+                return Err(format!("rank {} has {files} files", 8 - idx));
+            }
+        }
+
+        if kings != [1, 1] {
+            return Err("each side needs exactly one king".to_string());
+        }
+
+        if fields[1] != "w" && fields[1] != "b" {
+            return Err(format!("invalid side to move '{}'", fields[1]));
+        }
+
+        let rights = fields[2];
+        if rights != "-" && !rights.chars().all(|ch| "KQkqABCDEFGHabcdefgh".contains(ch)) {
+            return Err(format!("invalid castling rights '{rights}'"));
+        }
+
+        let ep = fields[3];
+        let valid_ep = matches!(ep.as_bytes(), [b'a'..=b'h', b'3' | b'6']);
+        if ep != "-" && !valid_ep {
+            return Err(format!("invalid en passant square '{ep}'"));
+        }
+
+        if let Some(halfm) = fields.get(4) {
+            halfm
+                .parse::<u8>()
+                .map_err(|_| format!("invalid halfmove clock '{halfm}'"))?;
+        }
+
+        if let Some(fullm) = fields.get(5) {
+            fullm
+                .parse::<u16>()
+                .map_err(|_| format!("invalid fullmove number '{fullm}'"))?;
+        }
+
+        let pos = Self::from_fen(fen);
+        let board = pos.board();
+        let them = board.stm() ^ 1;
+
+        if board.is_square_attacked(board.king_sq(them), them, board.occ()) {
+            return Err("side not to move is in check".to_string());
+        }
+
+        Ok(pos)
+    }
+
     pub fn map_legal_moves<F: FnMut(Move)>(&self, f: F) {
         self.board.map_legal_moves(&self.castling, f);
     }
 
+    /// Clears `buf` and refills it with the legal moves in this position,
+    /// so hot loops can reuse one allocation across calls.
+    pub fn collect_legal_moves_into(&self, buf: &mut Vec<Move>) {
+        buf.clear();
+        self.map_legal_moves(|mov| buf.push(mov));
+    }
+
     pub fn game_state(&self) -> GameState {
         self.board.game_state(&self.castling, &self.stack)
     }
@@ -163,6 +277,21 @@ impl ChessState {
         self.board.hash()
     }
 
+    /// Whether this position, with the same side to move, already occurred
+    /// since the last irreversible move, as in the draw check of
+    /// [`ChessState::game_state`].
+    pub fn is_repetition(&self) -> bool {
+        let hash = self.hash();
+
+        self.stack
+            .iter()
+            .rev()
+            .take(usize::from(self.board.halfm()) + 1)
+            .skip(1)
+            .step_by(2)
+            .any(|&prev| prev == hash)
+    }
+
     pub fn make_move(&mut self, mov: Move) {
         self.stack.push(self.board.hash());
         self.board.make(mov, &self.castling);
@@ -176,6 +305,50 @@ impl ChessState {
         self.board.stm()
     }
 
+    /// Whether `mov` captures a piece, including en passant.
+    pub fn is_capture(&self, mov: Move) -> bool {
+        mov.is_capture()
+    }
+
+    /// Whether the static exchange on the destination square of `mov`
+    /// wins at least `threshold`, in the units of `SEE_VALS`.
+    pub fn see_ge(&self, mov: Move, threshold: i32) -> bool {
+        see::greater_or_equal_to(&self.board, &mov, threshold)
+    }
+
+    /// Whether playing `mov` leaves the opponent in check.
+    pub fn gives_check(&self, mov: Move) -> bool {
+        let mut board = self.board;
+        board.make(mov, &self.castling);
+        board.in_check()
+    }
+
+    /// Number of pieces of type `piece` on the board, counting both sides.
+    pub fn piece_count(&self, piece: usize) -> u32 {
+        self.board.piece(piece).count_ones()
+    }
+
+    /// Total value of the knights, bishops, rooks and queens of both sides.
+    pub fn non_pawn_material(&self) -> i32 {
+        (Piece::KNIGHT..=Piece::QUEEN)
+            .map(|piece| SEE_VALS[piece] * self.piece_count(piece) as i32)
+            .sum()
+    }
+
+    /// Game phase from the remaining material: 1.0 with all pieces
+    /// on the board and 0.0 once only kings and pawns are left.
+    pub fn phase(&self) -> f32 {
+        const WEIGHTS: [u32; 4] = [1, 1, 2, 4];
+        const TOTAL: u32 = 24;
+
+        let phase: u32 = (Piece::KNIGHT..=Piece::QUEEN)
+            .zip(WEIGHTS)
+            .map(|(piece, weight)| weight * self.piece_count(piece))
+            .sum();
+
+        phase.min(TOTAL) as f32 / TOTAL as f32
+    }
+
     pub fn map_moves_with_policies<F: FnMut(Move, f32)>(&self, policy: &PolicyNetwork, mut f: F) {
         let hl = policy.hl(&self.board);
 
@@ -202,8 +375,16 @@ impl ChessState {
         &self,
         value: &ValueNetwork,
         params: &MctsParams,
+        cache: Option<&EvalCache>,
     ) -> (EvalWdl, EvalWdl, i32) {
-        let (win, draw, loss) = value.eval(&self.board);
+        let cached = cache.and_then(|cache| cache.get(self.hash()));
+        let (win, draw, loss) = cached.unwrap_or_else(|| {
+            let wdl = value.eval(&self.board);
+            if let Some(cache) = cache {
+                cache.push(self.hash(), wdl);
+            }
+            wdl
+        });
         let raw = EvalWdl::new(win, draw, loss);
 
         #[cfg(not(feature = "datagen"))]
@@ -237,11 +418,28 @@ impl ChessState {
         value: &ValueNetwork,
         params: &MctsParams,
         root_stm: usize,
+        cache: Option<&EvalCache>,
     ) -> EvalBreakdown {
-        let (raw, material, cp) = self.evaluate_material_wdl(value, params);
-        let contempt = params.contempt() as f32;
-        let perspective = if self.stm() == root_stm { 1.0 } else { -1.0 };
-        let contempt_scaled = material.apply_contempt(contempt * perspective);
+        let (raw, material, cp) = self.evaluate_material_wdl(value, params, cache);
+        // interpolate from full contempt with all pieces on the board down
+        // to `contempt_endgame_scale` of it with bare kings and pawns
+        let endgame_scale = params.contempt_endgame_scale();
+        let contempt =
+            params.contempt() as f32 * (endgame_scale + (1.0 - endgame_scale) * self.phase());
+        // contempt is normally for the side to move at the root, but can be
+        // fixed to white so that analysis scores don't swing between plies
+        let contempt_side = if params.contempt_white_perspective() != 0 {
+            Side::WHITE
+        } else {
+            root_stm
+        };
+        let perspective = if self.stm() == contempt_side {
+            1.0
+        } else {
+            -1.0
+        };
+        let contempt_scaled =
+            material.apply_contempt(contempt * perspective, params.contempt_max_delta());
 
         EvalBreakdown {
             raw,
@@ -252,12 +450,12 @@ impl ChessState {
     }
 
     pub fn get_value(&self, value: &ValueNetwork, params: &MctsParams) -> i32 {
-        let (_, _, cp) = self.evaluate_material_wdl(value, params);
+        let (_, _, cp) = self.evaluate_material_wdl(value, params, None);
         cp
     }
 
     pub fn get_value_wdl(&self, value: &ValueNetwork, params: &MctsParams, root_stm: usize) -> f32 {
-        self.eval_with_contempt(value, params, root_stm)
+        self.eval_with_contempt(value, params, root_stm, None)
             .contempt
             .score()
     }
@@ -266,7 +464,54 @@ impl ChessState {
         perft::<true, true>(&self.board, depth as u8, &self.castling)
     }
 
-    pub fn display(&self, policy: &PolicyNetwork) {
+    /// As [`ChessState::perft`], but with the root moves shared out between
+    /// `threads` and subtree counts cached in a `hash_mb` megabyte table,
+    /// which may be zero to disable it.
+    pub fn perft_parallel(&self, depth: usize, threads: usize, hash_mb: usize) -> u64 {
+        let depth = depth as u8;
+
+        if depth == 0 {
+            return 1;
+        }
+
+        let mut moves = Vec::new();
+        self.collect_legal_moves_into(&mut moves);
+
+        let table = PerftTable::new_mb(hash_mb);
+        let counts: Vec<AtomicU64> = moves.iter().map(|_| AtomicU64::new(0)).collect();
+        let next = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..threads.max(1) {
+                s.spawn(|| loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&mov) = moves.get(idx) else {
+                        break;
+                    };
+
+                    let mut tmp = self.board;
+                    tmp.make(mov, &self.castling);
+
+                    let num = perft_hashed(&tmp, depth - 1, &self.castling, &table);
+                    counts[idx].store(num, Ordering::Relaxed);
+                });
+            }
+        });
+
+        let mut total = 0;
+
+        for (mov, count) in moves.iter().zip(&counts) {
+            let num = count.load(Ordering::Relaxed);
+            println!("{}: {num}", mov.to_uci(&self.castling));
+            total += num;
+        }
+
+        total
+    }
+
+    /// The policy network's probability for each legal move, after a
+    /// softmax over the legal moves, most likely first.
+    pub fn policy_distribution(&self, policy: &PolicyNetwork) -> Vec<(Move, f32)> {
         let mut moves = Vec::new();
         let mut max = f32::NEG_INFINITY;
         self.map_moves_with_policies(policy, |mov, policy| {
@@ -288,6 +533,14 @@ impl ChessState {
             *policy /= total;
         }
 
+        moves.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        moves
+    }
+
+    pub fn display(&self, policy: &PolicyNetwork) {
+        let moves = self.policy_distribution(policy);
+
         let mut w = [0f32; 64];
         let mut count = [0; 64];
 
@@ -302,6 +555,53 @@ impl ChessState {
             count[to] += 1;
         }
 
+        self.print_board(|sq| {
+            (count[sq] > 0).then(|| {
+                let g = (255.0 * (2.0 * w[sq]).min(1.0)) as u8;
+                (255 - g, g, 0)
+            })
+        });
+    }
+
+    /// Colours each piece by how much the value network's evaluation
+    /// drops when it is removed from the board: green for pieces helping
+    /// the side to move, red for those helping the opponent. Kings and
+    /// empty squares are left uncoloured.
+    pub fn display_eval(&self, value: &ValueNetwork, params: &MctsParams) {
+        let base = self.get_value(value, params);
+        let mut delta = [None; 64];
+
+        for (sq, delta) in delta.iter_mut().enumerate() {
+            let pc = self.board.get_pc(1 << sq);
+            if pc == 0 || pc == Piece::KING {
+                continue;
+            }
+
+            let side = usize::from(self.board.piece(1) & (1 << sq) > 0);
+            let mut tmp = self.clone();
+            tmp.board.toggle(side, pc, sq as u16);
+
+            *delta = Some(base - tmp.get_value(value, params));
+        }
+
+        self.print_board(|sq| {
+            delta[sq].map(|cp| {
+                let strength = (cp.unsigned_abs() as f32 / 500.0).min(1.0);
+                let faded = (255.0 * (1.0 - strength)) as u8;
+                if cp >= 0 {
+                    (faded, 255, faded)
+                } else {
+                    (255, faded, faded)
+                }
+            })
+        });
+
+        println!("eval: {base}cp (side to move)");
+    }
+
+    /// Prints the board, with each square drawn in the given RGB colour
+    /// or in blue if it has none.
+    fn print_board<F: Fn(usize) -> Option<(u8, u8, u8)>>(&self, colour: F) {
         let pcs = [
             ['p', 'n', 'b', 'r', 'q', 'k'],
             ['P', 'N', 'B', 'R', 'Q', 'K'],
@@ -322,10 +622,8 @@ impl ChessState {
                     '.'
                 };
 
-                if count[sq] > 0 {
-                    let g = (255.0 * (2.0 * w[sq]).min(1.0)) as u8;
-                    let r = 255 - g;
-                    print!(" \x1b[38;2;{r};{g};0m{ch}\x1b[0m");
+                if let Some((r, g, b)) = colour(sq) {
+                    print!(" \x1b[38;2;{r};{g};{b}m{ch}\x1b[0m");
                 } else {
                     print!(" \x1b[34m{ch}\x1b[0m");
                 }
@@ -338,6 +636,86 @@ impl ChessState {
     }
 }
 
+#[derive(Default)]
+struct PerftEntry {
+    key: AtomicU64,
+    count: AtomicU64,
+}
+
+/// Subtree counts keyed on position hash and remaining depth. As in
+/// [`EvalCache`], the stored key is xored with the count, so an entry torn
+/// by a concurrent write misses rather than returning a wrong count.
+struct PerftTable {
+    table: Vec<PerftEntry>,
+}
+
+impl PerftTable {
+    fn new_mb(mb: usize) -> Self {
+        let entries = mb * 1024 * 1024 / std::mem::size_of::<PerftEntry>();
+
+        let mut table = Vec::new();
+        table.resize_with(entries, PerftEntry::default);
+
+        Self { table }
+    }
+
+    fn key(hash: u64, depth: u8) -> u64 {
+        hash ^ u64::from(depth).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn get(&self, hash: u64, depth: u8) -> Option<u64> {
+        if self.table.is_empty() {
+            return None;
+        }
+
+        let key = Self::key(hash, depth);
+        let entry = &self.table[(key % self.table.len() as u64) as usize];
+        let count = entry.count.load(Ordering::Relaxed);
+
+        (entry.key.load(Ordering::Relaxed) ^ count == key).then_some(count)
+    }
+
+    fn push(&self, hash: u64, depth: u8, count: u64) {
+        if self.table.is_empty() {
+            return;
+        }
+
+        let key = Self::key(hash, depth);
+        let entry = &self.table[(key % self.table.len() as u64) as usize];
+
+        entry.count.store(count, Ordering::Relaxed);
+        entry.key.store(key ^ count, Ordering::Relaxed);
+    }
+}
+
+fn perft_hashed(pos: &Position, depth: u8, castling: &Castling, table: &PerftTable) -> u64 {
+    let mut count = 0;
+
+    if depth == 0 {
+        return 1;
+    }
+
+    if depth == 1 {
+        pos.map_legal_moves(castling, |_| count += 1);
+        return count;
+    }
+
+    let hash = pos.hash();
+    if let Some(count) = table.get(hash, depth) {
+        return count;
+    }
+
+    pos.map_legal_moves(castling, |mov| {
+        let mut tmp = *pos;
+        tmp.make(mov, castling);
+        count += perft_hashed(&tmp, depth - 1, castling, table);
+    });
+
+    table.push(hash, depth, count);
+
+    count
+}
+
 fn perft<const ROOT: bool, const BULK: bool>(
     pos: &Position,
     depth: u8,
@@ -354,7 +732,7 @@ fn perft<const ROOT: bool, const BULK: bool>(
             let mut tmp = *pos;
             tmp.make(mov, castling);
 
-            let num = if !BULK && leaf {
+            let num = if leaf {
                 1
             } else {
                 perft::<false, BULK>(&tmp, depth - 1, castling)
@@ -370,3 +748,253 @@ fn perft<const ROOT: bool, const BULK: bool>(
 
     count
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase_is_one_at_startpos_and_zero_with_bare_kings() {
+        let startpos = ChessState::default();
+        assert_eq!(startpos.phase(), 1.0);
+        assert_eq!(startpos.piece_count(Piece::PAWN), 16);
+        assert_eq!(startpos.piece_count(Piece::QUEEN), 2);
+        assert_eq!(
+            startpos.non_pawn_material(),
+            2 * (2 * SEE_VALS[Piece::KNIGHT]
+                + 2 * SEE_VALS[Piece::BISHOP]
+                + 2 * SEE_VALS[Piece::ROOK]
+                + SEE_VALS[Piece::QUEEN])
+        );
+
+        let bare_kings = ChessState::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(bare_kings.phase(), 0.0);
+        assert_eq!(bare_kings.non_pawn_material(), 0);
+
+        // pawns don't count towards the phase
+        let pawns = ChessState::from_fen("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1");
+        assert_eq!(pawns.phase(), 0.0);
+    }
+
+    fn find_move(state: &ChessState, uci: &str) -> Move {
+        let mut found = None;
+        state.map_legal_moves(|mov| {
+            if state.conv_mov_to_str(mov) == uci {
+                found = Some(mov);
+            }
+        });
+
+        found.unwrap_or_else(|| panic!("{uci} is not legal"))
+    }
+
+    #[test]
+    fn classifies_captures_and_their_exchanges() {
+        // d5 is defended by the c6 pawn, and each white piece backs up the other
+        let state = ChessState::from_fen("4k3/8/2p5/3p4/4P3/8/8/3QK3 w - - 0 1");
+        let [pawn, queen] = [SEE_VALS[Piece::PAWN], SEE_VALS[Piece::QUEEN]];
+
+        let pawn_takes = find_move(&state, "e4d5");
+        assert!(state.is_capture(pawn_takes));
+        assert!(state.see_ge(pawn_takes, pawn));
+        assert!(!state.see_ge(pawn_takes, pawn + 1));
+
+        let queen_takes = find_move(&state, "d1d5");
+        assert!(state.is_capture(queen_takes));
+        assert!(!state.see_ge(queen_takes, 0));
+        assert!(state.see_ge(queen_takes, 2 * pawn - queen));
+        assert!(!state.see_ge(queen_takes, 2 * pawn - queen + 1));
+
+        let push = find_move(&state, "e4e5");
+        assert!(!state.is_capture(push));
+
+        let en_passant = ChessState::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        assert!(en_passant.is_capture(find_move(&en_passant, "e5d6")));
+        assert!(!en_passant.is_capture(find_move(&en_passant, "e5e6")));
+    }
+
+    #[test]
+    fn detects_direct_discovered_and_castling_checks() {
+        let queen = ChessState::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        assert!(queen.gives_check(find_move(&queen, "d1a4")));
+        assert!(queen.gives_check(find_move(&queen, "d1h5")));
+        assert!(!queen.gives_check(find_move(&queen, "d1d2")));
+
+        // the bishop moving off the e-file uncovers the rook
+        let discovered = ChessState::from_fen("4k3/8/8/8/4B3/8/8/K3R3 w - - 0 1");
+        assert!(discovered.gives_check(find_move(&discovered, "e4d3")));
+        assert!(!discovered.gives_check(find_move(&discovered, "a1b1")));
+
+        let castling = ChessState::from_fen("5k2/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert!(castling.gives_check(find_move(&castling, "e1g1")));
+        assert!(!castling.gives_check(find_move(&castling, "e1d1")));
+    }
+
+    #[test]
+    fn shuffling_knights_repeats_the_start_position() {
+        let mut state = ChessState::default();
+
+        for (i, uci) in ["g1f3", "g8f6", "f3g1", "f6g8"].into_iter().enumerate() {
+            assert!(!state.is_repetition(), "before move {i}");
+            state.make_move(find_move(&state, uci));
+        }
+
+        assert!(state.is_repetition());
+
+        // a pawn move resets the history
+        state.make_move(find_move(&state, "e2e4"));
+        state.make_move(find_move(&state, "e7e5"));
+        assert!(!state.is_repetition());
+    }
+
+    #[test]
+    fn policy_distribution_is_normalised_and_led_by_the_raw_argmax() {
+        let policy = PolicyNetwork::random_outputs(0x544);
+
+        for fen in [
+            ChessState::STARTPOS,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ] {
+            let state = ChessState::from_fen(fen);
+            let dist = state.policy_distribution(&policy);
+
+            let mut raw = Vec::new();
+            state.map_moves_with_policies(&policy, |mov, p| raw.push((mov, p)));
+            let argmax = raw.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap().0;
+
+            assert_eq!(dist.len(), raw.len());
+            let total: f32 = dist.iter().map(|&(_, p)| p).sum();
+            assert!((total - 1.0).abs() < 1e-4, "{fen}: {total}");
+            assert!(dist.windows(2).all(|w| w[0].1 >= w[1].1), "{fen}");
+            assert!(dist[0].1 > dist[dist.len() - 1].1, "{fen}");
+            assert_eq!(dist[0].0, argmax, "{fen}");
+        }
+    }
+
+    #[test]
+    fn parallel_perft_matches_serial_perft() {
+        const KIWIPETE: &str =
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+        // Kiwipete stops at depth 4, depth 5 is 193M nodes per run
+        let cases = [
+            (
+                ChessState::STARTPOS,
+                [20, 400, 8_902, 197_281, 4_865_609].as_slice(),
+            ),
+            (KIWIPETE, [48, 2_039, 97_862, 4_085_603].as_slice()),
+        ];
+
+        for (fen, counts) in cases {
+            let state = ChessState::from_fen(fen);
+
+            for (depth, &count) in (1..).zip(counts) {
+                assert_eq!(state.perft(depth), count, "{fen} depth {depth}");
+
+                // with and without the table, and with more threads than root moves
+                for (threads, hash_mb) in [(1, 0), (4, 1), (64, 16)] {
+                    assert_eq!(
+                        state.perft_parallel(depth, threads, hash_mb),
+                        count,
+                        "{fen} depth {depth}, {threads} threads, {hash_mb} MB"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn capped_contempt_never_flips_a_clearly_winning_move() {
+        const MAX_DELTA: f32 = 0.15;
+
+        let winning = EvalWdl::new(0.85, 0.12, 0.03);
+        let alternatives = [
+            EvalWdl::new(0.45, 0.5, 0.05),
+            EvalWdl::new(0.2, 0.75, 0.05),
+            EvalWdl::new(0.1, 0.3, 0.6),
+        ];
+
+        for contempt in (-1000..=1000).step_by(50) {
+            let contempt = contempt as f32;
+            let best = winning.apply_contempt(contempt, MAX_DELTA);
+            assert!((best.score() - winning.score()).abs() <= MAX_DELTA + 1e-5);
+
+            for alt in alternatives {
+                let other = alt.apply_contempt(contempt, MAX_DELTA);
+                assert!(
+                    best.score() > other.score(),
+                    "contempt {contempt}: {} <= {}",
+                    best.score(),
+                    other.score()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn contempt_scales_down_in_the_endgame() {
+        let (_, value) = crate::networks::zeroed();
+        let startpos = ChessState::default();
+        let rook_endgame = ChessState::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+
+        let applied = |state: &ChessState, params: &MctsParams| {
+            let eval = state.eval_with_contempt(value, params, Side::WHITE, None);
+            eval.contempt.score() - eval.material.score()
+        };
+
+        let mut params = MctsParams::default();
+        params.set("contempt", 200);
+
+        // the default scale leaves contempt flat
+        let flat = applied(&startpos, &params);
+        assert!(flat > 0.0);
+        assert!((applied(&rook_endgame, &params) - flat).abs() < 1e-6);
+
+        params.set("contempt_endgame_scale", 0);
+        assert!((applied(&startpos, &params) - flat).abs() < 1e-6);
+
+        let endgame = applied(&rook_endgame, &params);
+        assert!(endgame > 0.0 && endgame < flat, "{endgame} vs {flat}");
+    }
+
+    #[test]
+    fn white_perspective_contempt_agrees_with_the_mirrored_position() {
+        let (_, value) = crate::networks::zeroed();
+        let position = ChessState::from_fen("4k3/8/8/8/8/8/P7/R3K3 w - - 0 1");
+        let mirrored = ChessState::from_fen("r3k3/p7/8/8/8/8/8/4K3 b - - 0 1");
+
+        // contempt applied at the root, as a shift in white's score
+        let white_shift = |state: &ChessState, params: &MctsParams| {
+            let eval = state.eval_with_contempt(value, params, state.stm(), None);
+            let shift = eval.contempt.score() - eval.material.score();
+
+            if state.stm() == Side::WHITE {
+                shift
+            } else {
+                -shift
+            }
+        };
+
+        let mut params = MctsParams::default();
+        params.set("contempt", 200);
+
+        let ours = white_shift(&position, &params);
+        assert!(ours > 0.0);
+        assert!((white_shift(&mirrored, &params) + ours).abs() < 1e-6);
+
+        params.set("contempt_white_perspective", 1);
+        assert!((white_shift(&position, &params) - ours).abs() < 1e-6);
+        assert!((white_shift(&mirrored, &params) - ours).abs() < 1e-6);
+    }
+
+    #[test]
+    fn default_contempt_max_delta_leaves_contempt_uncapped() {
+        let max_delta = MctsParams::default().contempt_max_delta();
+        let wdl = EvalWdl::new(0.3, 0.6, 0.1);
+
+        for contempt in [-1000.0, -200.0, 50.0, 400.0, 1000.0] {
+            let capped = wdl.apply_contempt(contempt, max_delta);
+            let uncapped = wdl.apply_contempt(contempt, f32::INFINITY);
+            assert_eq!(capped.score(), uncapped.score(), "contempt {contempt}");
+        }
+    }
+}
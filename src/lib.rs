@@ -4,13 +4,26 @@ pub mod networks;
 pub mod tree;
 pub mod uci;
 
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
 use memmap2::Mmap;
+use sha2::{Digest, Sha256};
 
 pub struct MappedWeights<'a, T> {
     pub mmap: Mmap,  // The memory-mapped file
     pub data: &'a T, // A reference to the data in the mmap
 }
 
+impl<T> MappedWeights<'_, T> {
+    /// The mapped data, borrowed for no longer than the mapping is alive.
+    pub fn get(&self) -> &T {
+        self.data
+    }
+}
+
 #[macro_export]
 macro_rules! init {
     (|$sq:ident, $size:literal | $($rest:tt)+) => {{
@@ -63,28 +76,221 @@ pub unsafe fn boxed_and_zeroed<T>() -> Box<T> {
     }
 }
 
+/// Environment variable naming an extra directory to look for networks in.
+pub const NETWORK_DIR_VAR: &str = "MONTY_NETWORKS";
+
+/// Looks for the network file `name` in the working directory, then next
+/// to the executable, then in the directory named by [`NETWORK_DIR_VAR`].
+/// The error lists every directory that was searched.
+pub fn find_network(name: &str) -> io::Result<PathBuf> {
+    let mut dirs = vec![PathBuf::from(".")];
+
+    if let Some(dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        dirs.push(dir);
+    }
+
+    if let Some(dir) = std::env::var_os(NETWORK_DIR_VAR) {
+        dirs.push(PathBuf::from(dir));
+    }
+
+    if let Some(path) = dirs
+        .iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+    {
+        return Ok(path);
+    }
+
+    let searched = dirs
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "network file {name} not found in {searched}; \
+             put it in one of these or set {NETWORK_DIR_VAR} to its directory"
+        ),
+    ))
+}
+
 /// # Safety
 /// Only to be used internally.
 pub unsafe fn read_into_struct_unchecked<'a, T>(path: &str) -> MappedWeights<'a, T> {
-    let f = std::fs::File::open(path).unwrap();
-    let mmap = Mmap::map(&f).unwrap();
+    try_read_into_struct(path).unwrap_or_else(|err| panic!("{err}"))
+}
+
+fn network_file_name(path: &str) -> &str {
+    std::path::Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path)
+}
+
+/// The SHA-256 prefix in a `nn-<prefix>.network` file name, if `path`
+/// names a file of that form.
+pub fn network_name_hash(path: &str) -> Option<&str> {
+    network_file_name(path)
+        .strip_prefix("nn-")
+        .and_then(|name| name.strip_suffix(".network"))
+        .filter(|hash| !hash.is_empty() && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Like [`try_read_into_struct`], but also checks the mapped bytes against
+/// the SHA-256 prefix in the `nn-<prefix>.network` file name, so that a
+/// corrupted or truncated download is caught before it is used.
+///
+/// # Safety
+/// As for [`try_read_into_struct`].
+pub unsafe fn try_read_into_struct_verified<'a, T>(path: &str) -> io::Result<MappedWeights<'a, T>> {
+    let file_name = network_file_name(path);
+
+    let Some(expected) = network_name_hash(path) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{file_name} is not of the form nn-<sha256 prefix>.network"),
+        ));
+    };
+
+    let weights = try_read_into_struct(path)?;
+
+    let actual = format!("{:x}", Sha256::digest(&weights.mmap[..]));
+    if !actual.starts_with(expected) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{file_name} is corrupted, its SHA-256 is {}", &actual[..12]),
+        ));
+    }
+
+    Ok(weights)
+}
+
+/// Memory-maps the file at `path` as a `T`, returning an error instead of
+/// panicking if it cannot be opened or is not exactly the size of `T`.
+///
+/// # Safety
+/// Every bit pattern of the right size must be a valid `T`, which holds for
+/// the network structs as they contain nothing but integers and floats.
+pub unsafe fn try_read_into_struct<'a, T>(path: &str) -> io::Result<MappedWeights<'a, T>> {
+    let f = std::fs::File::open(path)?;
 
     let size = std::mem::size_of::<T>();
-    let file_size = mmap.len();
-    assert_eq!(
-        file_size, size,
-        "File size does not match the size of the structure"
-    );
+    let file_size = f.metadata()?.len();
+    if file_size != size as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("File size does not match the size of the structure ({file_size} != {size})"),
+        ));
+    }
+
+    let mmap = Mmap::map(&f)?;
 
     let ptr = mmap.as_ptr() as *const T;
 
     // Check if the pointer is properly aligned
     if !(ptr as usize).is_multiple_of(std::mem::align_of::<T>()) {
-        panic!("Memory is not properly aligned for the type");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Memory is not properly aligned for the type",
+        ));
     }
 
-    MappedWeights {
+    Ok(MappedWeights {
         mmap, // This ensures the memory is valid as long as MappedWeights exists
         data: &*ptr,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("monty-test-{}-{name}", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn wrong_sized_file_is_an_error() {
+        let words: Vec<u8> = (0..16u32).flat_map(u32::to_le_bytes).collect();
+
+        for (name, bytes) in [
+            ("short", &words[..56]),
+            ("long", &words[..]),
+            ("empty", &[][..]),
+        ] {
+            let path = temp_file(name, bytes);
+            let res = unsafe { try_read_into_struct::<[u32; 15]>(path.to_str().unwrap()) };
+            std::fs::remove_file(&path).unwrap();
+
+            let err = res.err().expect("a wrong-sized file should not load");
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{name}: {err}");
+        }
+
+        let path = temp_file("exact", &words);
+        let res = unsafe { try_read_into_struct::<[u32; 16]>(path.to_str().unwrap()) };
+        std::fs::remove_file(&path).unwrap();
+
+        let expected: [u32; 16] = std::array::from_fn(|i| i as u32);
+        assert_eq!(res.unwrap().get(), &expected);
+
+        let missing = std::env::temp_dir().join("monty-test-no-such-network");
+        let res = unsafe { try_read_into_struct::<[u32; 16]>(missing.to_str().unwrap()) };
+        assert_eq!(res.err().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn network_name_hash_reads_the_file_name() {
+        assert_eq!(
+            network_name_hash("nn-58274aa39e13.network"),
+            Some("58274aa39e13")
+        );
+        assert_eq!(
+            network_name_hash("/some/dir/nn-06e27b5ef6e7.network"),
+            Some("06e27b5ef6e7")
+        );
+        assert_eq!(network_name_hash("nn-.network"), None);
+        assert_eq!(network_name_hash("nn-58274aa39e1z.network"), None);
+        assert_eq!(network_name_hash("value.network"), None);
+        assert_eq!(network_name_hash("nn-58274aa39e13.network.zst"), None);
+    }
+
+    #[test]
+    fn verified_load_rejects_corrupted_files() {
+        // SHA-256 of the little-endian u32s 0 to 15
+        const HASH: &str = "5d85718ec594";
+
+        let words: Vec<u8> = (0..16u32).flat_map(u32::to_le_bytes).collect();
+        let dir = std::env::temp_dir().join(format!("monty-test-{}-verified", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("nn-{HASH}.network"));
+        let path_str = path.to_str().unwrap();
+
+        std::fs::write(&path, &words).unwrap();
+        let loaded = unsafe { try_read_into_struct_verified::<[u32; 16]>(path_str) };
+        assert_eq!(loaded.unwrap().get()[15], 15);
+
+        let mut corrupted = words.clone();
+        corrupted[17] ^= 1;
+        std::fs::write(&path, &corrupted).unwrap();
+        let err = unsafe { try_read_into_struct_verified::<[u32; 16]>(path_str) }
+            .err()
+            .expect("a corrupted file should not load");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let unnamed = dir.join("value.network");
+        std::fs::write(&unnamed, &words).unwrap();
+        let err = unsafe { try_read_into_struct_verified::<[u32; 16]>(unnamed.to_str().unwrap()) }
+            .err()
+            .expect("a file without a hash in its name should not load");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
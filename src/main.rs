@@ -236,6 +236,14 @@ mod net {
             return;
         }
 
+        if let Some("analyze-stream") = arg1.as_deref() {
+            let rest: Vec<String> = arg2.into_iter().chain(args).collect();
+            let commands: Vec<&str> = rest.iter().map(String::as_str).collect();
+
+            uci::analyze_stream(&commands, policy, value, &MctsParams::default());
+            return;
+        }
+
         let tcec_mode = matches!(arg1.as_deref(), Some("tcec"));
 
         uci::run(policy, value, tcec_mode);
@@ -245,9 +253,28 @@ mod net {
 #[cfg(not(feature = "embed"))]
 mod nonet {
     use monty::{
-        chess::ChessState, mcts::MctsParams, networks, read_into_struct_unchecked, uci,
-        MappedWeights,
+        chess::ChessState, find_network, mcts::MctsParams, networks, try_read_into_struct_verified,
+        uci, MappedWeights,
     };
+    use std::{io, process};
+
+    /// Finds and maps the network `name`, exiting with a one-line error
+    /// rather than starting up without it.
+    fn load_network<'a, T>(name: &str) -> MappedWeights<'a, T> {
+        let mapped = find_network(name).and_then(|path| {
+            let path = path.to_string_lossy();
+
+            // SAFETY: the network structs are valid for any bit pattern,
+            // and the file size is checked against the struct size
+            unsafe { try_read_into_struct_verified(&path) }
+                .map_err(|err| io::Error::new(err.kind(), format!("{path}: {err}")))
+        });
+
+        mapped.unwrap_or_else(|err| {
+            eprintln!("error: {err}");
+            process::exit(1);
+        })
+    }
 
     pub fn run() {
         let mut args = std::env::args();
@@ -256,10 +283,10 @@ mod nonet {
         let arg2 = args.next();
 
         let policy_mapped: MappedWeights<networks::PolicyNetwork> =
-            unsafe { read_into_struct_unchecked(networks::PolicyFileDefaultName) };
+            load_network(networks::PolicyFileDefaultName);
 
         let value_mapped: MappedWeights<networks::ValueNetwork> =
-            unsafe { read_into_struct_unchecked(networks::ValueFileDefaultName) };
+            load_network(networks::ValueFileDefaultName);
 
         let policy = policy_mapped.data;
         let value = value_mapped.data;
@@ -273,6 +300,14 @@ mod nonet {
             return;
         }
 
+        if let Some("analyze-stream") = arg1.as_deref() {
+            let rest: Vec<String> = arg2.into_iter().chain(args).collect();
+            let commands: Vec<&str> = rest.iter().map(String::as_str).collect();
+
+            uci::analyze_stream(&commands, policy, value, &MctsParams::default());
+            return;
+        }
+
         let tcec_mode = matches!(arg1.as_deref(), Some("tcec"));
 
         uci::run(policy, value, tcec_mode);
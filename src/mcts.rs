@@ -9,12 +9,17 @@ pub use search_stats::SearchStats;
 
 use crate::{
     chess::{GameState, Move},
-    networks::{PolicyNetwork, ValueNetwork},
+    networks::{EvalCache, PolicyNetwork, ValueNetwork},
     tree::{Node, NodePtr, Tree},
 };
 
+use rand::{rngs::StdRng, SeedableRng};
+
 use std::{
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
     thread,
     time::Instant,
 };
@@ -26,6 +31,8 @@ pub type SearchRet = (Move, f32, usize);
 pub type SearchRet = (Move, f32);
 
 pub static REPORT_ITERS: AtomicBool = AtomicBool::new(false);
+pub static SHOW_WDL: AtomicBool = AtomicBool::new(false);
+pub static SHOW_CURRLINE: AtomicBool = AtomicBool::new(false);
 
 fn calibrate_wdl(win: f32, draw: f32, loss: f32) -> [f32; 3] {
     const W: [[f64; 3]; 3] = [
@@ -62,22 +69,95 @@ fn calibrate_wdl(win: f32, draw: f32, loss: f32) -> [f32; 3] {
     [(e0 / sum) as f32, (e1 / sum) as f32, (e2 / sum) as f32]
 }
 
+/// Rounds a win, draw and loss to permille, leaving the draw to make up
+/// the rest so that the three always sum to 1000.
+fn wdl_permille(cal: [f32; 3]) -> [i32; 3] {
+    let win = ((cal[0] * 1000.0).round() as i32).clamp(0, 1000);
+    let loss = ((cal[2] * 1000.0).round() as i32).clamp(0, 1000 - win);
+
+    [win, 1000 - win - loss, loss]
+}
+
 #[derive(Clone, Copy)]
 pub struct Limits {
     pub max_time: Option<u128>,
     pub opt_time: Option<u128>,
     pub max_depth: usize,
     pub max_nodes: usize,
-    #[cfg(feature = "datagen")]
     pub kld_min_gain: Option<f64>,
+    /// Stop early once the best root move is reported at this many
+    /// centipawns or more, measured with the same mapping as `info score cp`
+    pub stop_score: Option<i32>,
+    /// Stop once a forced mate in at most this many moves is proven
+    pub mate: Option<usize>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Score {
+    Cp(i32),
+    /// Moves to mate, negative when the side to move is getting mated
+    Mate(i32),
+}
+
+/// How the line returned by [`Searcher::get_pv_full`] ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PvTermination {
+    /// The line reaches a node that has not been expanded yet
+    Horizon,
+    /// Moves to mate from the root, negative when the side to move at the
+    /// root is getting mated
+    Mate(i32),
+    /// The leaf position repeats one earlier in the game or the line
+    Repetition,
+    /// By the fifty-move rule, stalemate or lack of material
+    Draw,
+}
+
+/// One line of search progress, as printed in a UCI `info` line.
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: usize,
+    pub seldepth: usize,
+    /// Index of the line, starting from 1, when searching with multipv
+    pub multipv: usize,
+    pub score: Score,
+    /// Calibrated win, draw and loss out of 1000
+    pub wdl: [i32; 3],
+    pub time_ms: u128,
+    pub nodes: usize,
+    pub nps: usize,
+    pub hashfull: usize,
+    /// Policy of the first move in the line, in units of 0.01%
+    pub policy: i32,
+    pub pv: Vec<Move>,
+}
+
+pub type InfoCallback<'a> = &'a mut (dyn FnMut(SearchInfo) + Send);
+
+/// Main thread iterations between the root visit snapshots compared for
+/// `Limits::kld_min_gain`, matching the best move check. Datagen keeps
+/// comparing every iteration, which its threshold is tuned to.
+const KLD_INTERVAL: usize = if cfg!(feature = "datagen") { 1 } else { 128 };
+
+/// Visits the best root move needs before `Limits::stop_score` applies.
+const STOP_SCORE_MIN_VISITS: u64 = 4096;
+
+/// Consecutive checks `Limits::stop_score` must hold for before stopping.
+const STOP_SCORE_STABLE_CHECKS: u32 = 2;
+
 pub struct Searcher<'a> {
     tree: &'a Tree,
     params: &'a MctsParams,
     policy: &'a PolicyNetwork,
     value: &'a ValueNetwork,
     abort: &'a AtomicBool,
+    ponder: Option<&'a AtomicBool>,
+    eval_cache: Option<&'a EvalCache>,
+    mate_search: bool,
+    noise_seed: Option<u64>,
+    info_callback: Option<Mutex<InfoCallback<'a>>>,
+    /// Displayed centipawn score and wdl after `score_smoothing` is applied
+    smoothed_score: Mutex<Option<(f32, [f32; 3])>>,
 }
 
 impl<'a> Searcher<'a> {
@@ -94,20 +174,65 @@ impl<'a> Searcher<'a> {
             policy,
             value,
             abort,
+            ponder: None,
+            eval_cache: None,
+            mate_search: false,
+            noise_seed: None,
+            info_callback: None,
+            smoothed_score: Mutex::new(None),
         }
     }
 
+    /// Searches in ponder mode while `ponder` is set: the clock is held
+    /// at zero until it is cleared on `ponderhit`, after which the normal
+    /// time budget applies to the search that is already running.
+    pub fn with_ponder(mut self, ponder: &'a AtomicBool) -> Self {
+        self.ponder = Some(ponder);
+        self
+    }
+
+    /// Probes `cache` for value network outputs before running the network.
+    pub fn with_eval_cache(mut self, cache: &'a EvalCache) -> Self {
+        self.eval_cache = Some(cache);
+        self
+    }
+
+    /// Biases selection near the root towards checks and captures,
+    /// which forced mates run through.
+    pub fn with_mate_search(mut self, mate_search: bool) -> Self {
+        self.mate_search = mate_search;
+        self
+    }
+
+    /// Draws root dirichlet noise from a generator seeded with `seed`
+    /// rather than from the thread's, so noisy searches can be repeated.
+    pub fn with_noise_seed(mut self, seed: u64) -> Self {
+        self.noise_seed = Some(seed);
+        self
+    }
+
+    /// Passes each search report to `callback` rather than printing it,
+    /// whether or not UCI output is enabled. Reports are made from the
+    /// main search thread only.
+    pub fn with_info_callback(mut self, callback: InfoCallback<'a>) -> Self {
+        self.info_callback = Some(Mutex::new(callback));
+        self
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn playout_until_full_main(
         &self,
         limits: &Limits,
         timer: &Instant,
+        tm_timer: &mut Instant,
         #[cfg(not(feature = "uci-minimal"))] timer_last_output: &mut Instant,
         search_stats: &SearchStats,
         best_move: &mut Move,
         best_move_changes: &mut i32,
         previous_score: &mut f32,
-        #[cfg(feature = "datagen")] previous_kld: &mut Vec<i32>,
+        previous_kld: &mut Vec<i32>,
+        stop_score_checks: &mut u32,
+        next_time_check: &mut usize,
         #[cfg(not(feature = "uci-minimal"))] uci_output: bool,
         #[cfg(not(feature = "uci-minimal"))] multipv: usize,
         #[cfg(not(feature = "uci-minimal"))] gui_compatibility: bool,
@@ -117,14 +242,16 @@ impl<'a> Searcher<'a> {
             self.check_limits(
                 limits,
                 timer,
+                tm_timer,
                 #[cfg(not(feature = "uci-minimal"))]
                 timer_last_output,
                 search_stats,
                 best_move,
                 best_move_changes,
                 previous_score,
-                #[cfg(feature = "datagen")]
                 previous_kld,
+                stop_score_checks,
+                next_time_check,
                 #[cfg(not(feature = "uci-minimal"))]
                 uci_output,
                 #[cfg(not(feature = "uci-minimal"))]
@@ -190,54 +317,78 @@ impl<'a> Searcher<'a> {
         &self,
         limits: &Limits,
         timer: &Instant,
+        tm_timer: &mut Instant,
         #[cfg(not(feature = "uci-minimal"))] timer_last_output: &mut Instant,
         search_stats: &SearchStats,
         best_move: &mut Move,
         best_move_changes: &mut i32,
         previous_score: &mut f32,
-        #[cfg(feature = "datagen")] previous_kld_state: &mut Vec<i32>,
+        previous_kld_state: &mut Vec<i32>,
+        stop_score_checks: &mut u32,
+        next_time_check: &mut usize,
         #[cfg(not(feature = "uci-minimal"))] uci_output: bool,
         #[cfg(not(feature = "uci-minimal"))] multipv: usize,
         #[cfg(not(feature = "uci-minimal"))] gui_compatibility: bool,
     ) -> bool {
         let iters = search_stats.main_iters();
 
+        // time is only spent once the ponder move has been played
+        if self
+            .ponder
+            .is_some_and(|ponder| ponder.load(Ordering::Relaxed))
+        {
+            *tm_timer = Instant::now();
+        }
+
         if search_stats.total_iters() >= limits.max_nodes {
             return true;
         }
 
-        #[cfg(feature = "datagen")]
-        {
+        if let Some(max_moves) = limits.mate {
+            let root_state = self.tree[self.tree.root_node()].state();
+            if let GameState::Won(plies) = root_state {
+                if usize::from(plies).div_ceil(2) <= max_moves {
+                    return true;
+                }
+            }
+        }
+
+        if iters >= *next_time_check {
+            if let Some(time) = limits.max_time {
+                if tm_timer.elapsed().as_millis() >= time {
+                    return true;
+                }
+            }
+
+            *next_time_check =
+                iters + SearchHelpers::time_check_interval(self.params, iters, timer);
+        }
+
+        if iters.is_multiple_of(KLD_INTERVAL) {
             if let Some(min_gain) = limits.kld_min_gain {
                 let node = &self.tree[self.tree.root_node()];
                 let child_ptr = node.actions();
 
-                // Force i32 element type
-                let mut visit_dist: Vec<i32> = vec![0; node.num_actions()];
+                let mut visit_dist = [0; 256];
+                let visit_dist = &mut visit_dist[..node.num_actions()];
 
                 for (action, visits) in visit_dist.iter_mut().enumerate() {
                     let v = self.tree[child_ptr + action].visits();
-                    // Saturate to i32::MAX (works whether visits() is u64, or usize)
-                    let v_i32 = (v as i64).min(i32::MAX as i64) as i32;
-                    *visits = v_i32;
+                    *visits = v.min(i32::MAX as u64) as i32;
                 }
 
-                if let Some(kld_gain) = Node::kld_gain(&visit_dist, previous_kld_state) {
+                if let Some(kld_gain) = Node::kld_gain(visit_dist, previous_kld_state) {
                     if kld_gain < min_gain {
                         return true;
                     }
                 }
-                *previous_kld_state = visit_dist;
+
+                previous_kld_state.clear();
+                previous_kld_state.extend_from_slice(visit_dist);
             }
         }
 
         if iters.is_multiple_of(128) {
-            if let Some(time) = limits.max_time {
-                if timer.elapsed().as_millis() >= time {
-                    return true;
-                }
-            }
-
             let (_, new_best_move, _) = self.get_best_action(self.tree.root_node());
             if new_best_move != *best_move {
                 *best_move = new_best_move;
@@ -246,17 +397,37 @@ impl<'a> Searcher<'a> {
         }
 
         if iters.is_multiple_of(4096) {
+            if let Some(threshold) = limits.stop_score {
+                let (best_child_ptr, _, _) = self.get_best_action(self.tree.root_node());
+                let visits = self.tree[best_child_ptr].visits();
+                // child scores are displayed from the child's side, so negate
+                let cp = -self.get_display_score_for(best_child_ptr).0;
+
+                if cp >= threshold as f32 && visits >= STOP_SCORE_MIN_VISITS {
+                    *stop_score_checks += 1;
+                    if *stop_score_checks >= STOP_SCORE_STABLE_CHECKS {
+                        return true;
+                    }
+                } else {
+                    *stop_score_checks = 0;
+                }
+            }
+
             if let Some(time) = limits.opt_time {
                 let (should_stop, score) = SearchHelpers::soft_time_cutoff(
                     self,
-                    timer,
+                    tm_timer,
                     *previous_score,
                     *best_move_changes,
                     iters,
                     time,
                 );
 
-                if should_stop {
+                // below `tm_min_nodes` the visit distribution is too thin to
+                // trust, so only the hard limit may stop the search
+                let min_nodes = self.params.tm_min_nodes() as usize;
+
+                if should_stop && search_stats.total_iters() >= min_nodes {
                     return true;
                 }
 
@@ -315,6 +486,18 @@ impl<'a> Searcher<'a> {
         false
     }
 
+    /// Runs the search until a limit in `limits` is hit or it is aborted.
+    ///
+    /// Every thread checks `abort` after each playout, each of which ends
+    /// at a single leaf evaluation, so a stop is seen within one playout
+    /// per thread however deep the tree is. No tree flip is started once
+    /// it has been set.
+    ///
+    /// #### Note
+    /// With one thread, node or depth limits only, no root noise and an
+    /// empty tree, the best move, PV and root visit distribution are the
+    /// same on every run. Time limits and extra threads make the amount
+    /// of work done, and so the result, timing dependent.
     #[allow(clippy::too_many_arguments)]
     pub fn search(
         &self,
@@ -324,10 +507,12 @@ impl<'a> Searcher<'a> {
         multipv: usize,
         gui_compatibility: bool,
         update_nodes: &mut usize,
-        #[cfg(feature = "datagen")] use_dirichlet_noise: bool,
+        dirichlet_noise: Option<(f32, f32)>,
         #[cfg(feature = "datagen")] temp: f32,
     ) -> SearchRet {
         let timer = Instant::now();
+        let mut tm_timer = timer;
+        let report = uci_output || self.info_callback.is_some();
         #[cfg(not(feature = "uci-minimal"))]
         let mut timer_last_output = Instant::now();
 
@@ -335,6 +520,17 @@ impl<'a> Searcher<'a> {
         let root_stm = pos.stm();
         let node = self.tree.root_node();
 
+        if uci_output {
+            let mut root_moves = 0;
+            pos.map_legal_moves(|_| root_moves += 1);
+
+            if !self.tree.can_search(root_moves, threads) {
+                println!(
+                    "info string warning: hash too small to search with {threads} threads, increase Hash"
+                );
+            }
+        }
+
         // the root node is added to an empty tree, **and not counted** towards the
         // total node count, in order for `go nodes 1` to give the expected result
         if self.tree.is_empty() {
@@ -346,7 +542,7 @@ impl<'a> Searcher<'a> {
             self.tree
                 .expand_node(ptr, pos, self.params, self.policy, 1, 0);
 
-            let eval = pos.eval_with_contempt(self.value, self.params, root_stm);
+            let eval = pos.eval_with_contempt(self.value, self.params, root_stm, self.eval_cache);
             let root_score = eval.contempt.score();
             self.tree
                 .update_node_stats(ptr, 1.0 - root_score, eval.contempt.draw, 0);
@@ -372,13 +568,19 @@ impl<'a> Searcher<'a> {
             }
         }
 
-        // add dirichlet noise in datagen
-        #[cfg(feature = "datagen")]
-        if use_dirichlet_noise {
-            let alpha = 0.03;
-            let epsilon: f32 = if cfg!(feature = "policy") { 0.05 } else { 0.25 };
-
-            self.tree.add_dirichlet_noise_to_node(node, alpha, epsilon);
+        // add dirichlet noise to the root, given as (alpha, epsilon)
+        if let Some((alpha, epsilon)) = dirichlet_noise {
+            match self.noise_seed {
+                Some(seed) => {
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    self.tree
+                        .add_dirichlet_noise_to_node(node, alpha, epsilon, &mut rng);
+                }
+                None => {
+                    self.tree
+                        .add_dirichlet_noise_to_node(node, alpha, epsilon, &mut rand::rng());
+                }
+            }
         }
 
         let search_stats = SearchStats::new(threads);
@@ -387,26 +589,31 @@ impl<'a> Searcher<'a> {
         let mut best_move = Move::NULL;
         let mut best_move_changes = 0;
         let mut previous_score = f32::NEG_INFINITY;
-        #[cfg(feature = "datagen")]
         let mut previous_kld = Vec::new();
+        let mut stop_score_checks = 0;
+        let mut next_time_check = 0;
 
-        // search loop
+        // search loop, flipping tree halves whenever the current one fills up
         while !self.abort.load(Ordering::Relaxed) {
+            let iters_before = search_stats.total_iters();
+
             thread::scope(|s| {
                 s.spawn(|| {
                     self.playout_until_full_main(
                         &limits,
                         &timer,
+                        &mut tm_timer,
                         #[cfg(not(feature = "uci-minimal"))]
                         &mut timer_last_output,
                         stats_ref,
                         &mut best_move,
                         &mut best_move_changes,
                         &mut previous_score,
-                        #[cfg(feature = "datagen")]
                         &mut previous_kld,
+                        &mut stop_score_checks,
+                        &mut next_time_check,
                         #[cfg(not(feature = "uci-minimal"))]
-                        uci_output,
+                        report,
                         #[cfg(not(feature = "uci-minimal"))]
                         multipv,
                         #[cfg(not(feature = "uci-minimal"))]
@@ -420,16 +627,28 @@ impl<'a> Searcher<'a> {
                 }
             });
 
-            if !self.abort.load(Ordering::Relaxed) {
-                self.tree.flip(true);
+            if self.abort.load(Ordering::Relaxed) {
+                break;
+            }
+
+            // a half too small to hold the root's children fills up again
+            // straight after flipping, so flipping again would never finish
+            if search_stats.total_iters() == iters_before {
+                if uci_output {
+                    println!("info string warning: tree exhausted, increase Hash");
+                }
+
+                break;
             }
+
+            self.tree.flip(true);
         }
 
         self.tree.flush_root_accumulator();
 
         *update_nodes += search_stats.total_nodes();
 
-        if uci_output {
+        if report {
             self.search_report(
                 search_stats.avg_depth.load(Ordering::Relaxed).max(1),
                 search_stats.seldepth(),
@@ -494,52 +713,142 @@ impl<'a> Searcher<'a> {
                 nodes
             };
 
-            let nps = line_nodes as f32 / elapsed_secs;
+            let (mut scaled, mut cal) = if multipv > 1 {
+                self.get_display_score_for(pv_line.node)
+            } else {
+                self.get_display_score()
+            };
+
+            if multipv > 1 && pv_line.node != self.tree.root_node() {
+                scaled = -scaled;
+                cal = [cal[2], cal[1], cal[0]];
+            }
 
-            print!("info depth {line_depth} seldepth {line_seldepth} ");
-            if multipv > 1 {
-                print!("multipv {} ", idx + 1);
+            // multipv lines change order, so only a single line is smoothed
+            if multipv == 1 {
+                (scaled, cal) = self.smooth_display_score(scaled, cal);
             }
 
-            if pv_line.score > 1.0 {
-                print!("score mate {} ", pv_line.line.len().div_ceil(2));
-            } else if pv_line.score < 0.0 {
-                print!("score mate -{} ", pv_line.line.len() / 2);
+            let score = match self.mate_in_moves(pv_line.node) {
+                Some(moves) => Score::Mate(moves),
+                None => Score::Cp(scaled.round() as i32),
+            };
+
+            let info = SearchInfo {
+                depth: line_depth,
+                seldepth: line_seldepth,
+                multipv: idx + 1,
+                score,
+                wdl: wdl_permille(cal),
+                time_ms: ms,
+                nodes: line_nodes,
+                nps: (line_nodes as f32 / elapsed_secs) as usize,
+                hashfull: self.tree.fill_permille(),
+                policy: (pv_line.policy * 10000.0).round() as i32,
+                pv: pv_line.line.clone(),
+            };
+
+            if let Some(callback) = &self.info_callback {
+                (callback.lock().unwrap())(info);
             } else {
-                let (mut scaled, mut cal) = if multipv > 1 {
-                    self.get_display_score_for(pv_line.node)
-                } else {
-                    self.get_display_score()
-                };
+                self.print_info(&info, multipv, gui_compatibility);
+            }
+        }
 
-                if multipv > 1 && pv_line.node != self.tree.root_node() {
-                    scaled = -scaled;
-                    cal = [cal[2], cal[1], cal[0]];
-                }
+        if SHOW_CURRLINE.load(Ordering::Relaxed) {
+            print!("info currline");
+
+            for mov in self.current_line() {
+                print!(" {}", self.tree.root_position().conv_mov_to_str(mov));
+            }
 
-                print!("score cp {scaled:.0} ");
+            println!();
+        }
+    }
 
-                if !gui_compatibility {
-                    let wdl_i = cal.map(|v| (v * 1000.0).round() as i32);
-                    print!("wdl {} {} {} ", wdl_i[0], wdl_i[1], wdl_i[2]);
-                }
+    /// Blends a reported score into an exponential moving average of the
+    /// ones before it in this search, weighting the old average by
+    /// `score_smoothing`. Only the display is affected, never the search.
+    fn smooth_display_score(&self, scaled: f32, cal: [f32; 3]) -> (f32, [f32; 3]) {
+        let alpha = self.params.score_smoothing();
+        let mut smoothed = self.smoothed_score.lock().unwrap();
+
+        let new = match *smoothed {
+            Some((prev_scaled, prev_cal)) if alpha > 0.0 => {
+                let blend = |prev: f32, cur: f32| alpha * prev + (1.0 - alpha) * cur;
+                let cal = [0, 1, 2].map(|i| blend(prev_cal[i], cal[i]));
+                (blend(prev_scaled, scaled), cal)
             }
+            _ => (scaled, cal),
+        };
 
-            print!("time {ms} nodes {line_nodes} nps {nps:.0} ");
+        *smoothed = Some(new);
+        new
+    }
 
-            if !gui_compatibility {
-                let policy = (pv_line.policy * 10000.0).round();
-                print!("policy {policy:.0} ");
+    fn print_info(&self, info: &SearchInfo, multipv: usize, gui_compatibility: bool) {
+        print!("info depth {} seldepth {} ", info.depth, info.seldepth);
+        if multipv > 1 {
+            print!("multipv {} ", info.multipv);
+        }
+
+        match info.score {
+            Score::Mate(moves) => print!("score mate {moves} "),
+            Score::Cp(cp) => {
+                print!("score cp {cp} ");
+
+                if !gui_compatibility || SHOW_WDL.load(Ordering::Relaxed) {
+                    let [win, draw, loss] = info.wdl;
+                    print!("wdl {win} {draw} {loss} ");
+                }
             }
+        }
+
+        print!(
+            "time {} nodes {} nps {} ",
+            info.time_ms, info.nodes, info.nps
+        );
+        print!("hashfull {} ", info.hashfull);
+
+        if !gui_compatibility {
+            print!("policy {} ", info.policy);
+        }
+
+        print!("pv");
 
-            print!("pv");
+        for mov in &info.pv {
+            print!(" {}", self.tree.root_position().conv_mov_to_str(*mov));
+        }
+
+        println!();
+    }
+
+    /// The line the next iteration would descend, following PUCT selection
+    /// from the root without changing any node. With more than one thread
+    /// this is only an approximation.
+    fn current_line(&self) -> Vec<Move> {
+        const MAX_LEN: usize = 64;
 
-            for mov in &pv_line.line {
-                print!(" {}", self.tree.root_position().conv_mov_to_str(*mov));
+        let mut line = Vec::new();
+        let mut pos = self.tree.root_position().clone();
+        let mut ptr = self.tree.root_node();
+
+        while line.len() < MAX_LEN && self.tree[ptr].has_children() {
+            let node = &self.tree[ptr];
+            let action = iteration::pick_action(self, ptr, node, &pos, line.len() + 1);
+
+            if action == usize::MAX {
+                break;
             }
 
-            println!();
+            ptr = node.actions() + action;
+
+            let mov = self.tree[ptr].parent_move();
+            line.push(mov);
+            pos.make_move(mov);
         }
+
+        line
     }
 
     fn get_display_score(&self) -> (f32, [f32; 3]) {
@@ -586,7 +895,6 @@ impl<'a> Searcher<'a> {
         if children.is_empty() {
             return vec![PvLine {
                 line: Vec::new(),
-                score: 0.0,
                 policy: 0.0,
                 node: self.tree.root_node(),
                 depth,
@@ -644,8 +952,8 @@ impl<'a> Searcher<'a> {
         match node.state() {
             GameState::Lost(n) => 1.0 + f32::from(n),
             GameState::Won(n) => f32::from(n) - 256.0,
-            GameState::Draw => 0.5,
-            GameState::Ongoing => node.q(),
+            // a drawn node's Q is the draw score seen from its parent
+            GameState::Draw | GameState::Ongoing => node.q(),
         }
     }
 
@@ -659,11 +967,6 @@ impl<'a> Searcher<'a> {
         let mut pv = Vec::new();
         let mut ptr = start_ptr;
         let mut mov = start_move;
-        let score = if start_ptr.is_null() {
-            0.0
-        } else {
-            self.pv_score(start_ptr, self.tree[start_ptr].q())
-        };
 
         let mut pv_depth = 0;
         let mut pv_seldepth = 0;
@@ -686,7 +989,6 @@ impl<'a> Searcher<'a> {
 
         PvLine {
             line: pv,
-            score,
             policy,
             node: start_ptr,
             depth: pv_depth,
@@ -699,16 +1001,19 @@ impl<'a> Searcher<'a> {
         }
     }
 
-    fn pv_score(&self, ptr: NodePtr, q: f32) -> f32 {
+    /// Moves to mate after playing the root child `ptr`, negative when
+    /// the side to move at the root is the one getting mated.
+    fn mate_in_moves(&self, ptr: NodePtr) -> Option<i32> {
         if ptr.is_null() {
-            return q;
+            return None;
         }
 
+        // the child's state is from the opponent's perspective, and
+        // counts the plies after the root move has been played
         match self.tree[ptr].state() {
-            GameState::Lost(_) => 1.1,
-            GameState::Won(_) => -0.1,
-            GameState::Draw => 0.5,
-            GameState::Ongoing => q,
+            GameState::Lost(n) => Some(i32::from(n) / 2 + 1),
+            GameState::Won(n) => Some(-(i32::from(n) + 1) / 2),
+            _ => None,
         }
     }
 
@@ -727,13 +1032,233 @@ impl<'a> Searcher<'a> {
                 match child.state() {
                     GameState::Lost(n) => 1.0 + f32::from(n),
                     GameState::Won(n) => f32::from(n) - 256.0,
-                    GameState::Draw => 0.5,
-                    GameState::Ongoing => child.q(),
+                    GameState::Draw | GameState::Ongoing => child.q(),
                 }
             }
         })
     }
 
+    /// Expected reply to the best move, i.e. the second move of the reported PV.
+    pub fn ponder_move(&self) -> Option<Move> {
+        let (best_ptr, _, _) = self.get_best_action(self.tree.root_node());
+
+        if !self.tree[best_ptr].has_children() {
+            return None;
+        }
+
+        let (reply_ptr, reply, _) = self.get_best_action(best_ptr);
+        (self.tree[reply_ptr].visits() > 0).then_some(reply)
+    }
+
+    /// Samples a root move with probability proportional to `visits^(1 / temp)`,
+    /// where `rng` returns values uniform in [0, 1). A temperature of zero picks
+    /// the same move as `get_best_action`.
+    pub fn select_move_with_temperature(&self, temp: f32, rng: &mut impl FnMut() -> f32) -> Move {
+        let root = self.tree.root_node();
+        let (_, best_move, _) = self.get_best_action(root);
+
+        if temp <= 0.0 {
+            return best_move;
+        }
+
+        let node = &self.tree[root];
+        let child_ptr = node.actions();
+        let t = 1.0 / f64::from(temp);
+
+        let weights: Vec<f64> = (0..node.num_actions())
+            .map(|action| (self.tree[child_ptr + action].visits() as f64).powf(t))
+            .collect();
+
+        // overflow at very low temperatures leaves nothing to sample from
+        let total: f64 = weights.iter().sum();
+        if !total.is_normal() {
+            return best_move;
+        }
+
+        let target = f64::from(rng()) * total;
+        let mut cumulative = 0.0;
+
+        for (action, weight) in weights.iter().enumerate() {
+            cumulative += weight;
+
+            if cumulative > target {
+                return self.tree[child_ptr + action].parent_move();
+            }
+        }
+
+        best_move
+    }
+
+    /// Returns up to `count` root lines ranked by visits (ties broken by Q),
+    /// each paired with the Q of its first move from the root's perspective.
+    /// Each line follows the most visited child until an unexpanded node.
+    pub fn get_multipv(&self, count: usize) -> Vec<(Vec<Move>, f32)> {
+        let by_visits_then_q = |a: &NodePtr, b: &NodePtr| {
+            let (a, b) = (&self.tree[*a], &self.tree[*b]);
+            b.visits().cmp(&a.visits()).then(
+                b.q()
+                    .partial_cmp(&a.q())
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            )
+        };
+
+        let children_of = |ptr: NodePtr| {
+            let first_child_ptr = self.tree[ptr].actions();
+            (0..self.tree[ptr].num_actions())
+                .map(move |action| first_child_ptr + action)
+                .filter(|&child| self.tree[child].visits() > 0)
+        };
+
+        let mut roots: Vec<NodePtr> = children_of(self.tree.root_node()).collect();
+        roots.sort_by(by_visits_then_q);
+        roots.truncate(count);
+
+        roots
+            .into_iter()
+            .map(|start| {
+                let mut line = vec![self.tree[start].parent_move()];
+                let mut ptr = start;
+
+                while self.tree[ptr].has_children() {
+                    let Some(next) = children_of(ptr).min_by(by_visits_then_q) else {
+                        break;
+                    };
+
+                    ptr = next;
+                    line.push(self.tree[ptr].parent_move());
+                }
+
+                (line, self.tree[start].q())
+            })
+            .collect()
+    }
+
+    /// The principal variation, following the best child from the root
+    /// until an unexpanded or terminal node, and how it ends there.
+    pub fn get_pv_full(&self) -> (Vec<Move>, PvTermination) {
+        const MAX_LEN: usize = 256;
+
+        let mut line = Vec::new();
+        let mut ptr = self.tree.root_node();
+
+        while line.len() < MAX_LEN && self.tree[ptr].has_children() {
+            let (next_ptr, mov, _) = self.get_best_action(ptr);
+
+            if self.tree[next_ptr].visits() == 0 {
+                break;
+            }
+
+            line.push(mov);
+            ptr = next_ptr;
+        }
+
+        // the leaf's state is for its side to move, which is the root's
+        // side to move after an even number of plies
+        let plies = line.len() as i32;
+        let root_to_move = plies % 2 == 0;
+
+        let state = self.tree[ptr].state();
+        let termination = match state {
+            GameState::Ongoing => PvTermination::Horizon,
+            GameState::Draw => {
+                // replay the line over the root's history to tell
+                // repetitions apart from the other draws
+                let mut pos = self.tree.root_position().clone();
+                for &mov in &line {
+                    pos.make_move(mov);
+                }
+
+                if pos.is_repetition() {
+                    PvTermination::Repetition
+                } else {
+                    PvTermination::Draw
+                }
+            }
+            GameState::Lost(n) | GameState::Won(n) => {
+                let leaf_wins = matches!(state, GameState::Won(_));
+                let total = plies + i32::from(n);
+
+                if leaf_wins == root_to_move {
+                    PvTermination::Mate((total + 1) / 2)
+                } else {
+                    PvTermination::Mate(-total / 2)
+                }
+            }
+        };
+
+        (line, termination)
+    }
+
+    /// Prints the shape of the tree searched: its effective branching
+    /// factor, from node count and mean leaf depth, and how visits are
+    /// spread across nodes.
+    pub fn display_tree_shape(&self) {
+        let root = self.tree.root_node();
+        let half = root.half();
+
+        // log2 buckets of visit counts, 1, 2-3, 4-7, ...
+        let mut buckets = [0usize; 32];
+        let mut nodes = 0usize;
+        let mut leaves = 0usize;
+        let mut leaf_depths = 0usize;
+
+        let mut stack = vec![(root, 0usize)];
+
+        while let Some((ptr, depth)) = stack.pop() {
+            let node = &self.tree[ptr];
+            let visits = node.visits();
+
+            if visits == 0 {
+                continue;
+            }
+
+            nodes += 1;
+            buckets[(visits.ilog2() as usize).min(31)] += 1;
+
+            // children left in the other half may already be overwritten
+            let first_child_ptr = node.actions();
+            if !node.has_children() || first_child_ptr.half() != half {
+                leaves += 1;
+                leaf_depths += depth;
+                continue;
+            }
+
+            for action in 0..node.num_actions() {
+                stack.push((first_child_ptr + action, depth + 1));
+            }
+        }
+
+        let avg_depth = leaf_depths as f64 / leaves.max(1) as f64;
+        let ebf = if avg_depth > 0.0 {
+            (nodes as f64).powf(1.0 / avg_depth)
+        } else {
+            0.0
+        };
+
+        println!(
+            "info string tree nodes {nodes} leaves {leaves} avg leaf depth {avg_depth:.2} ebf {ebf:.2}"
+        );
+
+        let mut dist = String::new();
+        for (i, &count) in buckets.iter().enumerate().filter(|(_, &c)| c > 0) {
+            let low = 1u64 << i;
+            if low == 1 {
+                dist.push_str(&format!(" 1:{count}"));
+            } else {
+                dist.push_str(&format!(" {low}-{}:{count}", 2 * low - 1));
+            }
+        }
+        println!("info string tree visits{dist}");
+        println!("info string tree flips {}", self.tree.flips());
+
+        let root_visits = self.tree[root].visits();
+        if self.tree[root].has_children() && root_visits > 0 {
+            let (best_ptr, _, _) = self.get_best_action(root);
+            let share = self.tree[best_ptr].visits() as f64 / root_visits as f64;
+            println!("info string tree best move share {:.1}%", share * 100.0);
+        }
+    }
+
     pub fn display_moves(&self) {
         let first_child_ptr = self.tree[self.tree.root_node()].actions();
         for action in 0..self.tree[self.tree.root_node()].num_actions() {
@@ -754,10 +1279,271 @@ impl<'a> Searcher<'a> {
 
 struct PvLine {
     line: Vec<Move>,
-    score: f32,
     policy: f32,
     node: NodePtr,
     depth: usize,
     seldepth: usize,
     nodes: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chess::ChessState, networks};
+    use rand::Rng;
+
+    fn tree_for(fen: &str) -> Tree {
+        let mut tree = Tree::new_nodes(1 << 16, 1 << 12, 1);
+        tree.set_root_position(&ChessState::from_fen(fen));
+        tree
+    }
+
+    fn node_limits(nodes: usize) -> Limits {
+        Limits {
+            max_time: None,
+            opt_time: None,
+            max_depth: 256,
+            max_nodes: nodes,
+            kld_min_gain: None,
+            stop_score: None,
+            mate: None,
+        }
+    }
+
+    fn run(searcher: &Searcher, limits: Limits) -> Move {
+        let mut nodes = 0;
+
+        #[cfg(not(feature = "datagen"))]
+        let (mov, _) = searcher.search(1, limits, false, 1, false, &mut nodes, None);
+
+        #[cfg(feature = "datagen")]
+        let (mov, _, _) = searcher.search(1, limits, false, 1, false, &mut nodes, None, 0.0);
+
+        mov
+    }
+
+    #[test]
+    fn multipv_lines_start_with_different_moves() {
+        let tree = tree_for("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4");
+        let params = MctsParams::default();
+        let (policy, value) = networks::zeroed();
+        let abort = AtomicBool::new(false);
+        let searcher = Searcher::new(&tree, &params, policy, value, &abort);
+
+        run(&searcher, node_limits(400));
+
+        let lines = searcher.get_multipv(2);
+        assert_eq!(lines.len(), 2);
+        assert!(!lines[0].0.is_empty() && !lines[1].0.is_empty());
+        assert_ne!(lines[0].0[0], lines[1].0[0]);
+
+        // ranked by visits, and the best line is the one reported
+        let visits = |mov: Move| {
+            let root = tree.root_node();
+            let first = tree[root].actions();
+            (0..tree[root].num_actions())
+                .map(|action| &tree[first + action])
+                .find(|child| child.parent_move() == mov)
+                .map(|child| child.visits())
+                .unwrap()
+        };
+
+        assert!(visits(lines[0].0[0]) >= visits(lines[1].0[0]));
+    }
+
+    #[test]
+    fn multipv_returns_every_move_when_fewer_than_requested() {
+        // only Kb1 and h4 are legal
+        let tree = tree_for("7k/8/8/8/8/7P/2r5/K7 w - - 0 1");
+        let params = MctsParams::default();
+        let (policy, value) = networks::zeroed();
+        let abort = AtomicBool::new(false);
+        let searcher = Searcher::new(&tree, &params, policy, value, &abort);
+
+        run(&searcher, node_limits(200));
+
+        let lines = searcher.get_multipv(5);
+        assert_eq!(lines.len(), 2);
+        assert_ne!(lines[0].0[0], lines[1].0[0]);
+    }
+
+    #[test]
+    fn zero_temperature_selects_the_best_action() {
+        let tree = tree_for("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4");
+        let params = MctsParams::default();
+        let (policy, value) = networks::zeroed();
+        let abort = AtomicBool::new(false);
+        let searcher = Searcher::new(&tree, &params, policy, value, &abort);
+
+        run(&searcher, node_limits(400));
+
+        let (_, best, _) = searcher.get_best_action(tree.root_node());
+        let mut rng = || -> f32 { panic!("temperature 0 should not sample") };
+        assert_eq!(searcher.select_move_with_temperature(0.0, &mut rng), best);
+
+        // sampling only ever lands on visited children
+        let root = tree.root_node();
+        let first = tree[root].actions();
+        for target in [0.0, 0.25, 0.5, 0.75, 0.999] {
+            let mov = searcher.select_move_with_temperature(1.0, &mut || target);
+            let child = (0..tree[root].num_actions())
+                .map(|action| &tree[first + action])
+                .find(|child| child.parent_move() == mov)
+                .unwrap();
+            assert!(child.visits() > 0);
+        }
+    }
+
+    #[test]
+    fn kld_gain_stops_a_quiet_search_early() {
+        const NODE_CAP: usize = 100_000;
+
+        let tree = tree_for("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1");
+        let params = MctsParams::default();
+        let (policy, value) = networks::zeroed();
+        let abort = AtomicBool::new(false);
+        let searcher = Searcher::new(&tree, &params, policy, value, &abort);
+
+        let limits = Limits {
+            kld_min_gain: Some(0.001),
+            ..node_limits(NODE_CAP)
+        };
+
+        run(&searcher, limits);
+
+        let iters = tree[tree.root_node()].visits() as usize;
+        assert!(iters < NODE_CAP / 20, "searched {iters} nodes");
+    }
+
+    #[test]
+    fn wdl_permille_sums_to_1000() {
+        let mut cases = vec![[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.3335, 0.333, 0.3335]];
+
+        for w in 0..=20 {
+            for l in 0..=(20 - w) {
+                let (w, l) = (w as f32 / 20.0, l as f32 / 20.0);
+                cases.push(calibrate_wdl(w, 1.0 - w - l, l));
+            }
+        }
+
+        for cal in cases {
+            let wdl = wdl_permille(cal);
+            assert_eq!(wdl.iter().sum::<i32>(), 1000, "{cal:?} -> {wdl:?}");
+            assert!(
+                wdl.iter().all(|x| (0..=1000).contains(x)),
+                "{cal:?} -> {wdl:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn score_smoothing_blends_displayed_scores() {
+        let tree = tree_for(ChessState::STARTPOS);
+        let (policy, value) = networks::zeroed();
+        let abort = AtomicBool::new(false);
+
+        let params = MctsParams::default();
+        let searcher = Searcher::new(&tree, &params, policy, value, &abort);
+        searcher.smooth_display_score(100.0, [0.6, 0.3, 0.1]);
+        assert_eq!(
+            searcher.smooth_display_score(0.0, [0.2, 0.4, 0.4]),
+            (0.0, [0.2, 0.4, 0.4])
+        );
+
+        let mut params = MctsParams::default();
+        params.set("score_smoothing", 500);
+        let searcher = Searcher::new(&tree, &params, policy, value, &abort);
+
+        // the first report is shown as it is
+        assert_eq!(
+            searcher.smooth_display_score(100.0, [0.6, 0.3, 0.1]),
+            (100.0, [0.6, 0.3, 0.1])
+        );
+
+        let (scaled, cal) = searcher.smooth_display_score(0.0, [0.2, 0.4, 0.4]);
+        assert!((scaled - 50.0).abs() < 1e-3, "{scaled}");
+        for (got, want) in cal.into_iter().zip([0.4, 0.35, 0.25]) {
+            assert!((got - want).abs() < 1e-5, "{cal:?}");
+        }
+    }
+
+    #[test]
+    fn pv_into_a_forced_mate_ends_in_mate() {
+        let params = MctsParams::default();
+        let (policy, value) = networks::zeroed();
+
+        // Ra8# mates at once, Kb6 mates next move with Rh8#, and
+        // black's only move Kb8 walks into Rh8#
+        for (fen, moves) in [
+            ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1),
+            ("k7/8/2K5/8/8/8/8/7R w - - 0 1", 2),
+            ("k7/8/1K6/8/8/8/8/7R b - - 0 1", -1),
+        ] {
+            let tree = tree_for(fen);
+            let abort = AtomicBool::new(false);
+            let searcher = Searcher::new(&tree, &params, policy, value, &abort);
+            run(&searcher, node_limits(20_000));
+
+            let (line, termination) = searcher.get_pv_full();
+            assert_eq!(termination, PvTermination::Mate(moves), "{fen}");
+            let plies = if moves > 0 { 2 * moves - 1 } else { -2 * moves };
+            assert_eq!(line.len() as i32, plies, "{fen}");
+        }
+    }
+
+    #[test]
+    fn default_temperatures_play_the_best_move_and_higher_ones_vary_it() {
+        const FEN: &str = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+
+        let policy = networks::PolicyNetwork::random_outputs(0x557);
+        let (_, value) = networks::zeroed();
+
+        // PolicyTemp 1000 and MoveSelectTemp 0 are the defaults
+        let params = MctsParams::default();
+        assert_eq!(params.root_policy_temperature(), 1.0);
+        assert_eq!(params.move_select_temperature(), 0.0);
+
+        let searched = || {
+            let tree = tree_for(FEN);
+            let abort = AtomicBool::new(false);
+            let searcher = Searcher::new(&tree, &params, &policy, value, &abort);
+            let best = run(&searcher, node_limits(300));
+
+            let mut rng = || -> f32 { panic!("temperature 0 should not sample") };
+            let temp = params.move_select_temperature();
+            assert_eq!(searcher.select_move_with_temperature(temp, &mut rng), best);
+
+            (tree, best)
+        };
+
+        let (tree, best) = searched();
+        assert_eq!(searched().1, best);
+
+        let abort = AtomicBool::new(false);
+        let searcher = Searcher::new(&tree, &params, &policy, value, &abort);
+        let mut rng = StdRng::seed_from_u64(0x557);
+
+        // share of 1000 samples that play the best move
+        let mut best_share = |temp: f32| {
+            let mut moves = Vec::new();
+            let hits = (0..1000)
+                .filter(|_| {
+                    let mov = searcher.select_move_with_temperature(temp, &mut || rng.random());
+                    if !moves.contains(&mov) {
+                        moves.push(mov);
+                    }
+                    mov == best
+                })
+                .count();
+
+            (hits, moves.len())
+        };
+
+        let (cold, cold_moves) = best_share(0.25);
+        let (warm, warm_moves) = best_share(1.0);
+        let (hot, hot_moves) = best_share(4.0);
+
+        assert!(warm_moves > 1 && hot_moves >= warm_moves && warm_moves >= cold_moves);
+        assert!(cold > warm && warm > hot, "{cold} {warm} {hot}");
+    }
+}
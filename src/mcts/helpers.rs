@@ -5,13 +5,17 @@ use crate::{
     tree::Node,
 };
 
+/// Target spacing between checks of the hard time limit.
+const TIME_CHECK_MS: u128 = 2;
+
 pub struct SearchHelpers;
 
 impl SearchHelpers {
     /// CPUCT
     ///
-    /// Larger value implies more exploration.
-    pub fn get_cpuct(params: &MctsParams, node: &Node, is_root: bool) -> f32 {
+    /// Larger value implies more exploration. `total_visits` is the visit
+    /// count of the whole tree, used to narrow late searches.
+    pub fn get_cpuct(params: &MctsParams, node: &Node, is_root: bool, total_visits: u64) -> f32 {
         // baseline CPUCT value
         let mut cpuct = if is_root {
             params.root_cpuct()
@@ -30,6 +34,14 @@ impl SearchHelpers {
             cpuct *= 1.0 + params.cpuct_var_weight() * (frac - 1.0);
         }
 
+        // shrink CPUCT once the whole tree is large, to concentrate late
+        // visits on the best candidates
+        let decay_start = params.cpuct_decay_start() * 1024.0;
+        if total_visits as f32 > decay_start {
+            let growth = (total_visits as f32 / decay_start).ln();
+            cpuct /= 1.0 + params.cpuct_decay_rate() * growth;
+        }
+
         cpuct
     }
 
@@ -66,15 +78,24 @@ impl SearchHelpers {
         let t = scalar / (1.0 - params.winning_pst_threshold());
         let base_pst = 1.0 - params.base_pst_adjustment()
             + ((depth as f32) - params.root_pst_adjustment()).powf(-params.depth_pst_adjustment());
-        base_pst + (params.winning_pst_max() - base_pst) * t
+        let pst = base_pst + (params.winning_pst_max() - base_pst) * t;
+
+        // depth 1 is the root, which can be flattened or sharpened on its own
+        if depth == 1 {
+            pst * params.root_policy_temperature()
+        } else {
+            pst
+        }
     }
 
     /// First Play Urgency
     ///
+    /// Larger reduction implies less optimism for unvisited children.
+    ///
     /// #### Note
     /// Must return a value in [0, 1].
-    pub fn get_fpu(node: &Node) -> f32 {
-        1.0 - node.q()
+    pub fn get_fpu(params: &MctsParams, node: &Node) -> f32 {
+        (1.0 - node.q() - params.fpu_reduction()).clamp(0.0, 1.0)
     }
 
     /// Get a predicted win probability for an action
@@ -89,19 +110,41 @@ impl SearchHelpers {
         }
     }
 
+    /// Main thread iterations until the hard time limit is next checked
+    ///
+    /// #### Note
+    /// Unless fixed by `time_check_interval`, this is sized from the
+    /// iteration speed so far to land a check about every `TIME_CHECK_MS`,
+    /// so that fast machines don't overrun and slow ones don't over-check.
+    pub fn time_check_interval(params: &MctsParams, iters: usize, timer: &Instant) -> usize {
+        let fixed = params.time_check_interval();
+        if fixed > 0 {
+            return fixed as usize;
+        }
+
+        let iters_per_ms = iters as u128 / timer.elapsed().as_millis().max(1);
+        (iters_per_ms * TIME_CHECK_MS).clamp(1, 4096) as usize
+    }
+
     /// Calculates the maximum allowed time usage for a search
     ///
     /// #### Note
-    /// This will be overriden by a `go movetime` command,
-    /// and a move overhead will be applied to this, so no
-    /// need for it here.
+    /// This will be overriden by a `go movetime` command.
+    /// The move overhead is taken off the remaining time
+    /// before anything else, so both the optimal and the
+    /// maximum time are computed from the reduced budget.
     pub fn get_time(
         time: u64,
         increment: Option<u64>,
         ply: u32,
+        phase: f32,
         movestogo: Option<u64>,
+        move_overhead: u64,
         params: &MctsParams,
     ) -> (u128, u128) {
+        // never leave less than 10ms to search with
+        let time = time.saturating_sub(move_overhead).max(10);
+
         if let Some(mtg) = movestogo {
             // Cyclic time control (x moves in y seconds)
             let max_time = (time as f64 / (mtg as f64).clamp(1.0, 30.0)) as u128;
@@ -109,7 +152,11 @@ impl SearchHelpers {
         } else {
             // Increment time control (x seconds + y increment)
             let inc = increment.unwrap_or(0);
-            let mtg = params.tm_mtg() as u64;
+            // expect fewer moves to go as material comes off, from `tm_mtg`
+            // with all pieces on the board to `tm_mtg_endgame` in pawn endings
+            let mtg_endgame = f64::from(params.tm_mtg_endgame());
+            let mtg = (mtg_endgame + (f64::from(params.tm_mtg()) - mtg_endgame) * f64::from(phase))
+                .round() as u64;
 
             let time_left = (time + inc * (mtg - 1) - 10 * (2 + mtg)).max(1) as f64;
             let log_time = (time_left / 1000.0).log10();
@@ -154,7 +201,7 @@ impl SearchHelpers {
         let elapsed = timer.elapsed().as_millis();
 
         // Use more time if our eval is falling, and vice versa
-        let (score, _) = searcher.get_display_score();
+        let (score, wdl) = searcher.get_display_score();
         let eval_diff = if previous_score == f32::NEG_INFINITY {
             0.0
         } else {
@@ -178,9 +225,81 @@ impl SearchHelpers {
                 * searcher.params.tm_bmv4())
         .clamp(searcher.params.tm_bmv5(), searcher.params.tm_bmv6());
 
+        let wdl_factor = Self::wdl_time_factor(searcher.params, wdl);
+
         let total_time =
-            (time as f32 * falling_eval * best_move_instability * best_move_visits) as u128;
+            (time as f32 * falling_eval * best_move_instability * best_move_visits * wdl_factor)
+                as u128;
 
         (elapsed >= total_time, score)
     }
+
+    /// Use less time if the game is clearly decided, and more if it is
+    /// balanced with few draws, where extra search matters most
+    pub fn wdl_time_factor(params: &MctsParams, wdl: [f32; 3]) -> f32 {
+        let [win, draw, loss] = wdl;
+        let decided = (win - loss).abs();
+
+        1.0 + params.tm_wdl_balanced() * (1.0 - decided) * (1.0 - draw)
+            - params.tm_wdl_decided() * decided
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::GameState;
+
+    #[test]
+    fn fpu_reduction_lowers_unvisited_action_values() {
+        let parent = Node::new(GameState::Ongoing);
+        parent.update(0.4, 0.0);
+        let unvisited = Node::new(GameState::Ongoing);
+
+        let default = MctsParams::default();
+        let mut reduced = MctsParams::default();
+        reduced.set("fpu_reduction", 500);
+
+        let base =
+            SearchHelpers::get_action_value(&unvisited, SearchHelpers::get_fpu(&default, &parent));
+        let lower =
+            SearchHelpers::get_action_value(&unvisited, SearchHelpers::get_fpu(&reduced, &parent));
+
+        assert!((base - 0.6).abs() < 1e-3, "{base}");
+        assert!(lower < base, "{lower} >= {base}");
+        assert!((0.0..=1.0).contains(&lower));
+
+        // visited children keep their own value
+        let visited = Node::new(GameState::Ongoing);
+        visited.update(0.7, 0.0);
+        assert_eq!(
+            SearchHelpers::get_action_value(&visited, SearchHelpers::get_fpu(&reduced, &parent)),
+            visited.q()
+        );
+    }
+
+    #[test]
+    fn winning_root_gets_a_shorter_budget_than_a_balanced_one() {
+        let mut params = MctsParams::default();
+        params.set("tm_wdl_balanced", 300);
+        params.set("tm_wdl_decided", 300);
+
+        let winning = SearchHelpers::wdl_time_factor(&params, [0.95, 0.05, 0.0]);
+        let balanced = SearchHelpers::wdl_time_factor(&params, [0.4, 0.2, 0.4]);
+        let drawish = SearchHelpers::wdl_time_factor(&params, [0.1, 0.8, 0.1]);
+
+        assert!(winning < 1.0 && 1.0 < balanced, "{winning} {balanced}");
+        assert!(drawish < balanced, "{drawish} {balanced}");
+
+        // the defaults leave the budget alone
+        let default = MctsParams::default();
+        assert_eq!(
+            SearchHelpers::wdl_time_factor(&default, [0.95, 0.05, 0.0]),
+            1.0
+        );
+        assert_eq!(
+            SearchHelpers::wdl_time_factor(&default, [0.4, 0.2, 0.4]),
+            1.0
+        );
+    }
 }
@@ -54,7 +54,7 @@ pub fn perform_one(
 
         // select action to take via PUCT
         let stm = pos.stm();
-        let action = pick_action(searcher, ptr, node);
+        let action = pick_action(searcher, ptr, node, pos, *depth);
 
         let child_ptr = node.actions() + action;
 
@@ -115,32 +115,69 @@ fn get_utility(searcher: &Searcher, ptr: NodePtr, pos: &ChessState) -> (f32, f32
                 searcher.value,
                 searcher.params,
                 searcher.tree.root_position().stm(),
+                searcher.eval_cache,
             );
             (eval.contempt.score(), eval.contempt.draw)
         }
-        GameState::Draw => (0.5, 1.0),
+        // the draw score is from the root side's point of view
+        GameState::Draw => {
+            let draw_score = searcher.params.draw_score();
+            if pos.stm() == searcher.tree.root_position().stm() {
+                (draw_score, 1.0)
+            } else {
+                (1.0 - draw_score, 1.0)
+            }
+        }
         GameState::Lost(_) => (0.0, 0.0),
         GameState::Won(_) => (1.0, 0.0),
     }
 }
 
-fn pick_action(searcher: &Searcher, ptr: NodePtr, node: &Node) -> usize {
+pub(super) fn pick_action(
+    searcher: &Searcher,
+    ptr: NodePtr,
+    node: &Node,
+    pos: &ChessState,
+    depth: usize,
+) -> usize {
     let is_root = ptr == searcher.tree.root_node();
 
-    let cpuct = SearchHelpers::get_cpuct(searcher.params, node, is_root);
-    let fpu = SearchHelpers::get_fpu(node);
+    // in mate search, favour checks and captures near the root
+    let forcing_bonus =
+        if searcher.mate_search && depth <= searcher.params.mate_search_depth() as usize {
+            searcher.params.mate_search_bias()
+        } else {
+            0.0
+        };
+
+    let total_visits = searcher.tree[searcher.tree.root_node()].visits();
+    let cpuct = SearchHelpers::get_cpuct(searcher.params, node, is_root, total_visits);
+    let fpu = SearchHelpers::get_fpu(searcher.params, node);
     let expl_scale = SearchHelpers::get_explore_scaling(searcher.params, node);
 
     let expl = cpuct * expl_scale;
 
+    // the root can be given a wider policy cutoff than the rest of the tree
+    let (top_p, min_actions) = if is_root {
+        (
+            searcher.params.root_policy_top_p(),
+            searcher.params.root_min_policy_actions(),
+        )
+    } else {
+        (
+            searcher.params.policy_top_p(),
+            searcher.params.min_policy_actions(),
+        )
+    };
+
     let actions_ptr = node.actions();
     let mut acc = 0.0;
     let mut k = 0;
-    while k < node.num_actions() && acc < searcher.params.policy_top_p() {
+    while k < node.num_actions() && acc < top_p {
         acc += searcher.tree[actions_ptr + k].policy();
         k += 1;
     }
-    let mut limit = k.max(searcher.params.min_policy_actions() as usize);
+    let mut limit = k.max(min_actions as usize);
     let mut thresh = 1u64 << (searcher.params.visit_threshold_power() as u32);
     while node.visits() >= thresh && limit < node.num_actions() {
         limit += 2;
@@ -153,17 +190,25 @@ fn pick_action(searcher: &Searcher, ptr: NodePtr, node: &Node) -> usize {
         .get_best_child_by_key_lim(ptr, limit, |child| {
             let mut q = SearchHelpers::get_action_value(child, fpu);
 
-            // virtual loss
+            // virtual loss, `virtual_loss_count` units per thread in flight
             let threads = f64::from(child.threads());
             if threads > 0.0 {
                 let visits = child.visits() as f64;
+                let units = f64::from(searcher.params.virtual_loss_count()) * (threads - 1.0);
                 let q2 = f64::from(q) * visits
-                    / (visits + 1.0 + searcher.params.virtual_loss_weight() * (threads - 1.0));
+                    / (visits + 1.0 + searcher.params.virtual_loss_weight() * units);
                 q = q2 as f32;
             }
 
             let u = expl * child.policy() / (1 + child.visits()) as f32;
 
+            if forcing_bonus > 0.0 {
+                let mov = child.parent_move();
+                if pos.is_capture(mov) || pos.gives_check(mov) {
+                    q += forcing_bonus;
+                }
+            }
+
             q + u
         })
 }
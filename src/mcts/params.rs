@@ -1,3 +1,8 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+};
+
 #[derive(Clone)]
 struct Param<T> {
     val: T,
@@ -16,6 +21,14 @@ impl Param<i32> {
         self.val = val.clamp(self.min, self.max);
     }
 
+    fn set_scaled(&mut self, val: f64) {
+        self.set(val.round() as i32);
+    }
+
+    fn scaled(&self) -> String {
+        self.val.to_string()
+    }
+
     fn info(&self, name: &str) {
         println!(
             "option name {} type spin default {:.0} min {:.0} max {:.0}",
@@ -37,6 +50,16 @@ impl Param<f32> {
         self.val = actual.clamp(self.min, self.max);
     }
 
+    fn set_scaled(&mut self, val: f64) {
+        let actual = (val / 1000.0) as f32;
+        self.val = actual.clamp(self.min, self.max);
+    }
+
+    // scaled in f64, where it is exact, so `set_scaled` gets back `val`
+    fn scaled(&self) -> String {
+        (f64::from(self.val) * 1000.0).to_string()
+    }
+
     fn info(&self, name: &str) {
         println!(
             "option name {} type spin default {:.0} min {:.0} max {:.0}",
@@ -66,6 +89,15 @@ impl Param<f64> {
         self.val = actual.clamp(self.min, self.max);
     }
 
+    fn set_scaled(&mut self, val: f64) {
+        let actual = val / 1000.0;
+        self.val = actual.clamp(self.min, self.max);
+    }
+
+    fn scaled(&self) -> String {
+        (self.val * 1000.0).to_string()
+    }
+
     fn info(&self, name: &str) {
         println!(
             "option name {} type spin default {:.0} min {:.0} max {:.0}",
@@ -125,6 +157,49 @@ macro_rules! make_mcts_params {
             pub fn list_spsa(&self) {
                 $(self.$name.list(stringify!($name), $step, $r);)*
             }
+
+            /// Writes every param as a `name value` line, in the units
+            /// `set` takes, so floats are given in thousandths.
+            pub fn save(&self, path: &str) -> io::Result<()> {
+                let mut file = BufWriter::new(File::create(path)?);
+                $(writeln!(file, "{} {}", stringify!($name), self.$name.scaled())?;)*
+                file.flush()
+            }
+
+            /// Reads params written by [`MctsParams::save`], or `name, value`
+            /// lines as tuning outputs them. Unlike `set`, values are not
+            /// rounded to whole thousandths, so a saved set loads back as it
+            /// was, up to float rounding. Blank lines and `#` comments are
+            /// skipped, and bad lines are warned about.
+            pub fn load(&mut self, path: &str) -> io::Result<()> {
+                let reader = BufReader::new(File::open(path)?);
+
+                for line in reader.lines() {
+                    let line = line?;
+                    let line = line.trim();
+
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+
+                    let mut parts = line
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|part| !part.is_empty());
+
+                    let name = parts.next().unwrap_or_default();
+                    let Some(val) = parts.next().and_then(|val| val.parse::<f64>().ok()) else {
+                        println!("info string malformed param line \"{line}\"");
+                        continue;
+                    };
+
+                    match name {
+                        $(stringify!($name) => self.$name.set_scaled(val),)*
+                        _ => println!("info string unknown param {name}"),
+                    }
+                }
+
+                Ok(())
+            }
         }
     };
 }
@@ -141,6 +216,8 @@ make_mcts_params! {
     cpuct_var_scale: f32 = 0.28056, 0.0, 2.0, 0.02, 0.002;
     cpuct_var_warmup: f32 = 0.50087, 0.0, 1.0, 0.01, 0.002;
     cpuct_visits_scale: f32 = 36.931, 1.0, 512.0, 3.2, 0.002;
+    cpuct_decay_start: f32 = 1024.0, 1.0, 65536.0, 100.0, 0.0; //Do not tune this value!
+    cpuct_decay_rate: f32 = 0.0, 0.0, 1.0, 0.02, 0.0; //Do not tune this value!
     expl_tau: f32 = 0.670, 0.1, 1.0, 0.05, 0.002;
     gini_base: f32 = 0.4662, 0.2, 2.0, 0.0679, 0.002;
     gini_ln_multiplier: f32 = 1.568, 0.4, 3.0, 0.1634, 0.002;
@@ -163,6 +240,7 @@ make_mcts_params! {
     tm_bonus_value1: f64 = 0.4536, 0.1, 2.0, 0.05, 0.002;
     tm_max_time: f64 = 0.876, 0.400, 0.990, 0.085, 0.002;
     tm_mtg: i32 = 28, 10, 60, 3, 0.002;
+    tm_mtg_endgame: i32 = 28, 10, 60, 3, 0.002;
     tm_falling_eval1: f32 = 0.05428, 0.0, 0.2, 0.007, 0.002;
     tm_falling_eval2: f32 = 0.7255, 0.1, 1.0, 0.06, 0.002;
     tm_falling_eval3: f32 = 1.6281, 0.1, 3.0, 0.18, 0.002;
@@ -175,11 +253,80 @@ make_mcts_params! {
     tm_bmv4: f32 = 2.5457, 0.1, 8.0, 0.4, 0.002;
     tm_bmv5: f32 = 0.6282, 0.1, 1.0, 0.055, 0.002;
     tm_bmv6: f32 = 1.9047, 0.1, 3.0, 0.15, 0.002;
+    tm_wdl_balanced: f32 = 0.0, 0.0, 0.5, 0.04, 0.002;
+    tm_wdl_decided: f32 = 0.0, 0.0, 0.5, 0.04, 0.002;
+    tm_min_nodes: i32 = 0, 0, 1048576, 4096, 0.0; //Do not tune this value!
     butterfly_reduction_factor: i32 = 8140, 1, 65536, 819, 0.002;
     butterfly_policy_divisor: i32 = 16405, 1, 131072, 1638, 0.002;
     policy_top_p: f32 = 0.703, 0.1, 1.0, 0.05, 0.002;
     min_policy_actions: i32 = 6, 1, 32, 1, 0.002;
+    root_policy_top_p: f32 = 0.703, 0.1, 1.0, 0.05, 0.002;
+    root_min_policy_actions: i32 = 6, 1, 32, 1, 0.002;
+    expand_policy_threshold: f32 = 0.0, 0.0, 0.1, 0.001, 0.0; //Do not tune this value!
     visit_threshold_power: i32 = 3, 0, 8, 1, 0.002;
     virtual_loss_weight: f64 = 2.501, 1.0, 5.0, 0.25, 0.002;
+    virtual_loss_count: i32 = 1, 1, 16, 1, 0.0; //Do not tune this value!
+    fpu_reduction: f32 = 0.0, 0.0, 0.5, 0.02, 0.002;
+    mate_search_bias: f32 = 0.1, 0.0, 1.0, 0.01, 0.0; //Do not tune this value!
+    mate_search_depth: i32 = 4, 1, 16, 1, 0.0; //Do not tune this value!
     contempt: i32 = 0, -1000, 1000, 10, 0.0; //Do not tune this value!
+    contempt_max_delta: f32 = 1.0, 0.0, 1.0, 0.01, 0.0; //Do not tune this value!
+    contempt_endgame_scale: f32 = 1.0, 0.0, 1.0, 0.05, 0.0; //Do not tune this value!
+    contempt_white_perspective: i32 = 0, 0, 1, 1, 0.0; //Do not tune this value!
+    root_policy_temperature: f32 = 1.0, 0.1, 10.0, 0.1, 0.0; //Do not tune this value!
+    move_select_temperature: f32 = 0.0, 0.0, 10.0, 0.1, 0.0; //Do not tune this value!
+    draw_score: f32 = 0.5, 0.0, 1.0, 0.01, 0.0; //Do not tune this value!
+    score_smoothing: f32 = 0.0, 0.0, 0.99, 0.01, 0.0; //Do not tune this value!
+    time_check_interval: i32 = 0, 0, 65536, 1, 0.0; //Do not tune this value!
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("monty-test-{}-{name}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn save_then_load_reproduces_every_param() {
+        let defaults = temp_path("params-defaults");
+        MctsParams::default().save(&defaults).unwrap();
+        let default_text = std::fs::read_to_string(&defaults).unwrap();
+
+        // move every param off its default, by a fraction of a thousandth
+        // where the type allows it, plus a line `load` should only warn about
+        let mut perturbed_text = String::from("# tuned\nnot_a_param 5\n\n");
+        for line in default_text.lines() {
+            let (name, val) = line.split_once(' ').unwrap();
+            let val: f64 = val.parse().unwrap();
+            perturbed_text += &format!("{name}, {}\n", val * 0.9 + 0.37);
+        }
+
+        let perturbed = temp_path("params-perturbed");
+        std::fs::write(&perturbed, perturbed_text).unwrap();
+
+        let mut params = MctsParams::default();
+        params.load(&perturbed).unwrap();
+
+        let saved = temp_path("params-saved");
+        params.save(&saved).unwrap();
+        let saved_text = std::fs::read_to_string(&saved).unwrap();
+
+        let mut reloaded = MctsParams::default();
+        reloaded.load(&saved).unwrap();
+
+        let resaved = temp_path("params-resaved");
+        reloaded.save(&resaved).unwrap();
+        let resaved_text = std::fs::read_to_string(&resaved).unwrap();
+
+        for path in [defaults, perturbed, saved, resaved] {
+            let _ = std::fs::remove_file(path);
+        }
+
+        assert_ne!(saved_text, default_text);
+        assert_eq!(saved_text.lines().count(), default_text.lines().count());
+        assert_eq!(resaved_text, saved_text);
+    }
 }
@@ -1,8 +1,10 @@
+mod cache;
 pub mod common;
 pub mod policy;
 pub mod value;
 
-pub use common::Accumulator;
+pub use cache::EvalCache;
+pub use common::{Accumulator, WeightStats};
 
 // Choose the file name type based on the feature
 #[cfg(feature = "datagen")]
@@ -17,3 +19,20 @@ pub use value::ValueFileDefaultName;
 
 pub use policy::{PolicyNetwork, L1 as POLICY_L1};
 pub use value::ValueNetwork;
+
+/// Networks with every weight zero, shared between tests so that search
+/// can run without the network files. Every legal move gets the same
+/// policy and every position evaluates to an even win, draw and loss.
+#[cfg(test)]
+pub(crate) fn zeroed() -> (&'static PolicyNetwork, &'static ValueNetwork) {
+    use std::sync::OnceLock;
+
+    static NETWORKS: OnceLock<(Box<PolicyNetwork>, Box<ValueNetwork>)> = OnceLock::new();
+
+    let (policy, value) = NETWORKS.get_or_init(|| {
+        // both networks are plain arrays of numbers, so valid when zeroed
+        unsafe { (crate::boxed_and_zeroed(), crate::boxed_and_zeroed()) }
+    });
+
+    (policy.as_ref(), value.as_ref())
+}
@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+#[derive(Default)]
+struct EvalCacheEntry {
+    key: AtomicU64,
+    win_draw: AtomicU64,
+    loss: AtomicU32,
+}
+
+/// Raw `(win, draw, loss)` outputs of the value network, keyed on the
+/// position hash. Unlike the search hash table this is never cleared
+/// between searches, as the network output for a position never changes.
+///
+/// #### Note
+/// Entries are read and written without locking. The stored key is the
+/// position hash xored with the data, so an entry torn by a concurrent
+/// write fails the key check and is treated as a miss.
+pub struct EvalCache {
+    table: Vec<EvalCacheEntry>,
+    hits: AtomicUsize,
+}
+
+impl EvalCache {
+    pub fn new_mb(mb: usize) -> Self {
+        let entries = mb * 1024 * 1024 / std::mem::size_of::<EvalCacheEntry>();
+
+        let mut table = Vec::new();
+        table.resize_with(entries, EvalCacheEntry::default);
+
+        Self {
+            table,
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    /// Drops every entry, needed whenever the value network changes.
+    pub fn clear(&mut self) {
+        self.table.fill_with(EvalCacheEntry::default);
+    }
+
+    /// Number of successful probes since creation.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn get(&self, hash: u64) -> Option<(f32, f32, f32)> {
+        if self.table.is_empty() {
+            return None;
+        }
+
+        let entry = &self.table[(hash % self.table.len() as u64) as usize];
+
+        let key = entry.key.load(Ordering::Relaxed);
+        let win_draw = entry.win_draw.load(Ordering::Relaxed);
+        let loss = entry.loss.load(Ordering::Relaxed);
+
+        if key != Self::key(hash, win_draw, loss) {
+            return None;
+        }
+
+        self.hits.fetch_add(1, Ordering::Relaxed);
+
+        Some((
+            f32::from_bits(win_draw as u32),
+            f32::from_bits((win_draw >> 32) as u32),
+            f32::from_bits(loss),
+        ))
+    }
+
+    pub fn push(&self, hash: u64, wdl: (f32, f32, f32)) {
+        if self.table.is_empty() {
+            return;
+        }
+
+        let entry = &self.table[(hash % self.table.len() as u64) as usize];
+
+        let win_draw = u64::from(wdl.0.to_bits()) | (u64::from(wdl.1.to_bits()) << 32);
+        let loss = wdl.2.to_bits();
+
+        entry.win_draw.store(win_draw, Ordering::Relaxed);
+        entry.loss.store(loss, Ordering::Relaxed);
+        entry
+            .key
+            .store(Self::key(hash, win_draw, loss), Ordering::Relaxed);
+    }
+
+    fn key(hash: u64, win_draw: u64, loss: u32) -> u64 {
+        hash ^ win_draw ^ u64::from(loss)
+    }
+}
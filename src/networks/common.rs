@@ -30,6 +30,12 @@ impl<const N: usize> Accumulator<i16, N> {
     pub fn add_multi(&mut self, adds: &[usize], weights: &[Self]) {
         const REGS: usize = 8;
         const PER: usize = REGS * 16;
+        const {
+            assert!(
+                N.is_multiple_of(PER),
+                "N must be a multiple of the block size"
+            )
+        };
 
         let mut regs = [0i16; PER];
 
@@ -59,6 +65,12 @@ impl<const N: usize> Accumulator<i16, N> {
     pub fn add_multi_i8(&mut self, adds: &[usize], weights: &[Accumulator<i8, N>]) {
         const REGS: usize = 8;
         const PER: usize = REGS * 16;
+        const {
+            assert!(
+                N.is_multiple_of(PER),
+                "N must be a multiple of the block size"
+            )
+        };
 
         let mut regs = [0i16; PER];
 
@@ -182,3 +194,145 @@ pub struct TransposedLayer<T: Copy, const M: usize, const N: usize> {
     pub weights: [Accumulator<T, M>; N],
     pub biases: Accumulator<T, N>,
 }
+
+/// Summary of the stored values of a layer, in raw (quantised) units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeightStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// Count of NaN or infinite values, only possible in float layers.
+    pub non_finite: usize,
+}
+
+impl WeightStats {
+    fn from_values<T: Copy + Into<f32>>(values: impl Iterator<Item = T>) -> Self {
+        let mut min = f32::INFINITY;
+        let mut max = f32::NEG_INFINITY;
+        let mut sum = 0.0f64;
+        let mut count = 0usize;
+        let mut non_finite = 0;
+
+        for value in values {
+            let value = value.into();
+
+            if !value.is_finite() {
+                non_finite += 1;
+                continue;
+            }
+
+            min = min.min(value);
+            max = max.max(value);
+            sum += f64::from(value);
+            count += 1;
+        }
+
+        Self {
+            min,
+            max,
+            mean: (sum / count.max(1) as f64) as f32,
+            non_finite,
+        }
+    }
+}
+
+impl<T: Copy + Into<f32>, const N: usize> Accumulator<T, N> {
+    pub fn stats(accs: &[Self]) -> WeightStats {
+        WeightStats::from_values(accs.iter().flat_map(|acc| acc.0.iter().copied()))
+    }
+}
+
+impl<T: Copy + Into<f32>, const M: usize, const N: usize> Layer<T, M, N> {
+    pub fn stats(&self) -> WeightStats {
+        let weights = self.weights.iter().flat_map(|acc| acc.0.iter().copied());
+        WeightStats::from_values(weights.chain(self.biases.0.iter().copied()))
+    }
+}
+
+impl<T: Copy + Into<f32>, const M: usize, const N: usize> TransposedLayer<T, M, N> {
+    pub fn stats(&self) -> WeightStats {
+        let weights = self.weights.iter().flat_map(|acc| acc.0.iter().copied());
+        WeightStats::from_values(weights.chain(self.biases.0.iter().copied()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const FEATURES: usize = 64;
+
+    // plain per-element sums, without the blocking of `add_multi*`
+    fn reference<T: Copy + Into<i16>, const N: usize>(
+        base: &Accumulator<i16, N>,
+        adds: &[usize],
+        weights: &[Accumulator<T, N>],
+    ) -> Accumulator<i16, N> {
+        let mut res = *base;
+
+        for (j, elem) in res.0.iter_mut().enumerate() {
+            for &add in adds {
+                *elem += weights[add].0[j].into();
+            }
+        }
+
+        res
+    }
+
+    fn random_adds(rng: &mut StdRng) -> Vec<usize> {
+        let count = rng.random_range(0..=32);
+        (0..count).map(|_| rng.random_range(0..FEATURES)).collect()
+    }
+
+    fn check_add_multi<const N: usize>(rng: &mut StdRng) {
+        // kept small enough that 32 additions can't overflow
+        let weights: Vec<Accumulator<i16, N>> = (0..FEATURES)
+            .map(|_| Accumulator(std::array::from_fn(|_| rng.random_range(-512..512))))
+            .collect();
+
+        for _ in 0..32 {
+            let base = Accumulator(std::array::from_fn(|_| rng.random_range(-512..512)));
+            let adds = random_adds(rng);
+
+            let mut blocked = base;
+            blocked.add_multi(&adds, &weights);
+
+            assert!(blocked.0 == reference(&base, &adds, &weights).0);
+        }
+    }
+
+    fn check_add_multi_i8<const N: usize>(rng: &mut StdRng) {
+        let weights: Vec<Accumulator<i8, N>> = (0..FEATURES)
+            .map(|_| Accumulator(std::array::from_fn(|_| rng.random())))
+            .collect();
+
+        for _ in 0..32 {
+            let base = Accumulator(std::array::from_fn(|_| i16::from(rng.random::<i8>())));
+            let adds = random_adds(rng);
+
+            let mut blocked = base;
+            blocked.add_multi_i8(&adds, &weights);
+
+            assert!(blocked.0 == reference(&base, &adds, &weights).0);
+        }
+    }
+
+    #[test]
+    fn add_multi_matches_scalar_sums() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        check_add_multi::<128>(&mut rng);
+        check_add_multi::<256>(&mut rng);
+        check_add_multi::<1024>(&mut rng);
+    }
+
+    #[test]
+    fn add_multi_i8_matches_scalar_sums() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        check_add_multi_i8::<128>(&mut rng);
+        check_add_multi_i8::<256>(&mut rng);
+        check_add_multi_i8::<1024>(&mut rng);
+    }
+}
@@ -4,7 +4,7 @@ pub mod see;
 
 use montyformat::chess::{Move, Position};
 
-use super::common::{Accumulator, Layer, TransposedLayer};
+use super::common::{Accumulator, Layer, TransposedLayer, WeightStats};
 
 // DO NOT MOVE
 #[allow(non_upper_case_globals, dead_code)]
@@ -34,6 +34,12 @@ pub struct PolicyNetwork {
 }
 
 impl PolicyNetwork {
+    /// Per-layer statistics of the raw weights and biases, used to spot
+    /// corrupt or mismatched network files.
+    pub fn layer_stats(&self) -> [(&'static str, WeightStats); 2] {
+        [("l1", self.l1.stats()), ("l2", self.l2.stats())]
+    }
+
     pub fn hl(&self, pos: &Position) -> Accumulator<i16, { L1 / 2 }> {
         let mut l1 = Accumulator([0; L1]);
 
@@ -65,6 +71,17 @@ impl PolicyNetwork {
         res
     }
 
+    /// Where `mov` lands in the output layer, as `(see_bucket, base_index,
+    /// final_index)`, for checking which SEE block a move's policy is
+    /// read from.
+    pub fn index_breakdown(pos: &Position, mov: Move) -> (usize, usize, usize) {
+        let see_bucket = outputs::see_bucket(pos, mov);
+        let base_index = outputs::base_index(pos, mov);
+        let final_index = outputs::map_move_to_index(pos, mov);
+
+        (see_bucket, base_index, final_index)
+    }
+
     pub fn get(&self, pos: &Position, mov: &Move, hl: &Accumulator<i16, { L1 / 2 }>) -> f32 {
         let idx = outputs::map_move_to_index(pos, *mov);
         let weights = &self.l2.weights[idx];
@@ -77,4 +94,172 @@ impl PolicyNetwork {
 
         (res as f32 / f32::from(QA * FACTOR) + f32::from(self.l2.biases.0[idx])) / f32::from(QB)
     }
+
+    /// Network with every hidden neuron saturated and random weights from
+    /// the first few of them to each move, so that moves get distinct but
+    /// not extreme logits whatever the position.
+    #[cfg(test)]
+    pub(crate) fn random_outputs(seed: u64) -> Box<Self> {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut net: Box<Self> = unsafe { crate::boxed_and_zeroed() };
+
+        net.l1.biases.0.fill(i8::MAX);
+
+        for row in net.l2.weights.iter_mut() {
+            for w in row.0.iter_mut().take(16) {
+                *w = rng.random();
+            }
+        }
+
+        net
+    }
+
+    /// Network that rates `mov` in `pos` far above every other move, which
+    /// all get a logit of zero.
+    #[cfg(test)]
+    pub(crate) fn favouring(pos: &Position, mov: Move) -> Box<Self> {
+        let mut net: Box<Self> = unsafe { crate::boxed_and_zeroed() };
+
+        net.l1.biases.0.fill(i8::MAX);
+
+        let idx = outputs::map_move_to_index(pos, mov);
+        for w in net.l2.weights[idx].0.iter_mut().take(16) {
+            *w = i8::MAX;
+        }
+
+        net
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::{Castling, ChessState};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn random_positions(rng: &mut StdRng, count: usize) -> Vec<(Position, Castling)> {
+        (0..count)
+            .map(|_| {
+                let mut castling = Castling::default();
+                let mut pos = Position::parse_fen(ChessState::STARTPOS, &mut castling);
+
+                for _ in 0..rng.random_range(0..60) {
+                    let mut moves = Vec::new();
+                    pos.map_legal_moves(&castling, |mov| moves.push(mov));
+
+                    if moves.is_empty() {
+                        break;
+                    }
+
+                    pos.make(moves[rng.random_range(0..moves.len())], &castling);
+                }
+
+                (pos, castling)
+            })
+            .collect()
+    }
+
+    // plain per-neuron sums, without the blocking of `add_multi_i8`
+    fn reference_hl(net: &PolicyNetwork, pos: &Position) -> Vec<i16> {
+        let mut l1: Vec<i32> = net.l1.biases.0.iter().map(|&b| i32::from(b)).collect();
+
+        inputs::map_features(pos, |feat| {
+            for (v, &w) in l1.iter_mut().zip(net.l1.weights[feat].0.iter()) {
+                *v += i32::from(w);
+            }
+        });
+
+        (0..L1 / 2)
+            .map(|j| {
+                let i = l1[j].clamp(0, i32::from(QA));
+                let k = l1[j + L1 / 2].clamp(0, i32::from(QA));
+                ((i * k) / i32::from(QA / FACTOR)) as i16
+            })
+            .collect()
+    }
+
+    fn reference_get(net: &PolicyNetwork, pos: &Position, mov: Move, hl: &[i16]) -> f32 {
+        let idx = outputs::map_move_to_index(pos, mov);
+
+        let res: i32 = net.l2.weights[idx]
+            .0
+            .iter()
+            .zip(hl.iter())
+            .map(|(&w, &v)| i32::from(w) * i32::from(v))
+            .sum();
+
+        (res as f32 / f32::from(QA * FACTOR) + f32::from(net.l2.biases.0[idx])) / f32::from(QB)
+    }
+
+    #[test]
+    fn hl_and_get_match_scalar_reference() {
+        let mut rng = StdRng::seed_from_u64(0x9011c7);
+        let positions = random_positions(&mut rng, 24);
+
+        let mut net: Box<PolicyNetwork> = unsafe { crate::boxed_and_zeroed() };
+
+        // only the rows these positions touch get random weights, which
+        // keeps the setup cheap without changing what is summed
+        let mut filled_l1 = vec![false; INPUT_SIZE];
+        let mut filled_l2 = vec![false; outputs::NUM_MOVES_INDICES];
+
+        for b in net.l1.biases.0.iter_mut() {
+            *b = rng.random_range(-64..=127);
+        }
+
+        for (pos, castling) in &positions {
+            inputs::map_features(pos, |feat| {
+                if !filled_l1[feat] {
+                    filled_l1[feat] = true;
+                    for w in net.l1.weights[feat].0.iter_mut() {
+                        *w = rng.random_range(-16..16);
+                    }
+                }
+            });
+
+            pos.map_legal_moves(castling, |mov| {
+                let idx = outputs::map_move_to_index(pos, mov);
+                if !filled_l2[idx] {
+                    filled_l2[idx] = true;
+                    net.l2.biases.0[idx] = rng.random();
+                    for w in net.l2.weights[idx].0.iter_mut() {
+                        *w = rng.random();
+                    }
+                }
+            });
+        }
+
+        for (pos, castling) in &positions {
+            let hl = net.hl(pos);
+            let expected = reference_hl(&net, pos);
+            assert!(hl.0[..] == expected[..]);
+
+            pos.map_legal_moves(castling, |mov| {
+                let got = net.get(pos, &mov, &hl);
+                let want = reference_get(&net, pos, mov, &expected);
+                assert_eq!(got, want, "{}", mov.to_uci(castling));
+            });
+        }
+    }
+
+    #[test]
+    fn index_breakdown_splits_captures_by_see() {
+        // exd5 is an even trade, Qxd5 loses the queen to cxd5
+        let mut castling = Castling::default();
+        let pos = Position::parse_fen("4k3/8/2p5/3p4/4P3/8/8/3QK3 w - - 0 1", &mut castling);
+
+        let mut moves = Vec::new();
+        pos.map_legal_moves(&castling, |mov| moves.push(mov));
+        let find = |uci: &str| *moves.iter().find(|m| m.to_uci(&castling) == uci).unwrap();
+
+        let (good_bucket, good_base, good_index) =
+            PolicyNetwork::index_breakdown(&pos, find("e4d5"));
+        let (bad_bucket, bad_base, bad_index) = PolicyNetwork::index_breakdown(&pos, find("d1d5"));
+
+        assert_eq!((good_bucket, bad_bucket), (1, 0));
+        assert_eq!(good_index, outputs::FROM_TO + good_base);
+        assert_eq!(bad_index, bad_base);
+    }
 }
@@ -10,21 +10,30 @@ pub const NUM_MOVES_INDICES: usize = 2 * FROM_TO;
 pub const FROM_TO: usize = OFFSETS[5][64] + PROMOS + 2 + 8;
 pub const PROMOS: usize = 4 * 22;
 
+/// Moves with a SEE of at least this use the second block of outputs.
+pub const SEE_THRESHOLD: i32 = -108;
+
 pub fn map_move_to_index(pos: &Position, mov: Move) -> usize {
+    FROM_TO * see_bucket(pos, mov) + base_index(pos, mov)
+}
+
+/// Which block of `FROM_TO` outputs the move uses: 1 if it passes SEE
+/// against `SEE_THRESHOLD`, else 0.
+pub fn see_bucket(pos: &Position, mov: Move) -> usize {
+    usize::from(see::greater_or_equal_to(pos, &mov, SEE_THRESHOLD))
+}
+
+/// Index of the move within its SEE block, after mirroring to the side
+/// to move and the king's half of the board.
+pub fn base_index(pos: &Position, mov: Move) -> usize {
     let hm = if pos.king_index() % 8 > 3 { 7 } else { 0 };
     let flip = hm ^ if pos.stm() == Side::BLACK { 56 } else { 0 };
 
     let src = usize::from(mov.src() ^ flip);
     let dst = usize::from(mov.to() ^ flip);
 
-    let good_see = usize::from(see::greater_or_equal_to(pos, &mov, -108));
-
-    let idx = if mov.is_promo() {
-        let ffile = src % 8;
-        let tfile = dst % 8;
-        let promo_id = 2 * ffile + tfile;
-
-        OFFSETS[5][64] + (PROMOS / 4) * (mov.promo_pc() - Piece::KNIGHT) + promo_id
+    if mov.is_promo() {
+        OFFSETS[5][64] + promo_index(src % 8, dst % 8, mov.promo_pc())
     } else if mov.flag() == Flag::QS || mov.flag() == Flag::KS {
         let is_ks = usize::from(mov.flag() == Flag::KS);
         let is_hm = usize::from(hm == 0);
@@ -36,11 +45,41 @@ pub fn map_move_to_index(pos: &Position, mov: Move) -> usize {
         let below = DESTINATIONS[src][pc] & ((1 << dst) - 1);
 
         OFFSETS[pc][src] + below.count_ones() as usize
-    };
+    }
+}
 
-    FROM_TO * good_see + idx
+/// Index of a promotion within the `PROMOS` block, from the files the
+/// pawn moves between and the piece it promotes to.
+///
+/// #### Note
+/// The layout of this block is baked into the policy net, so any change
+/// here needs a retrained net. The assertion below checks at compile
+/// time that every promotion maps to a unique index inside the block.
+const fn promo_index(ffile: usize, tfile: usize, promo_pc: usize) -> usize {
+    (PROMOS / 4) * (promo_pc - Piece::KNIGHT) + 2 * ffile + tfile
 }
 
+const _: () = {
+    let mut seen = [false; PROMOS];
+
+    let mut pc = Piece::KNIGHT;
+    while pc <= Piece::QUEEN {
+        let mut ffile = 0;
+        while ffile < 8 {
+            let mut tfile = if ffile == 0 { 0 } else { ffile - 1 };
+            while tfile <= ffile + 1 && tfile < 8 {
+                let idx = promo_index(ffile, tfile, pc);
+                assert!(idx < PROMOS, "promotion index out of bounds");
+                assert!(!seen[idx], "promotion index collision");
+                seen[idx] = true;
+                tfile += 1;
+            }
+            ffile += 1;
+        }
+        pc += 1;
+    }
+};
+
 macro_rules! init {
     (|$sq:ident, $size:literal | $($rest:tt)+) => {{
         let mut $sq = 0;
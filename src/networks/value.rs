@@ -3,7 +3,7 @@ pub mod threats;
 
 use montyformat::chess::Position;
 
-use super::common::{Accumulator, Layer, SCReLU, TransposedLayer};
+use super::common::{Accumulator, Layer, SCReLU, TransposedLayer, WeightStats};
 
 // DO NOT MOVE
 #[allow(non_upper_case_globals, dead_code)]
@@ -28,6 +28,18 @@ pub struct ValueNetwork {
 }
 
 impl ValueNetwork {
+    /// Per-layer statistics of the raw weights and biases, used to spot
+    /// corrupt or mismatched network files.
+    pub fn layer_stats(&self) -> [(&'static str, WeightStats); 5] {
+        [
+            ("pst", Accumulator::stats(&self.pst)),
+            ("l1", self.l1.stats()),
+            ("l2", self.l2.stats()),
+            ("l3", self.l3.stats()),
+            ("l4", self.l4.stats()),
+        ]
+    }
+
     pub fn eval(&self, board: &Position) -> (f32, f32, f32) {
         let mut pst = Accumulator([0.0; 3]);
 
@@ -13,7 +13,7 @@ use std::{
     mem::MaybeUninit,
     ops::Index,
     ptr,
-    sync::atomic::{AtomicBool, AtomicI16, AtomicU64, Ordering},
+    sync::atomic::{AtomicBool, AtomicI16, AtomicU64, AtomicUsize, Ordering},
 };
 
 use crate::{
@@ -29,6 +29,9 @@ const ROOT_ACCUM_EAGER_LIMIT: u64 = 256;
 const NODE_BATCH_THRESHOLD: u64 = 16384;
 const MAX_BATCHED_NODES: usize = 32;
 const BATCH_SLOT_RESERVED: u64 = u64::MAX - 1;
+const DEFAULT_AUTO_MB: usize = 64;
+const MIN_AUTO_MB: usize = 1;
+const MAX_AUTO_MB: usize = 524288;
 
 #[repr(align(64))]
 struct RootAccumulatorEntry {
@@ -281,6 +284,12 @@ impl ButterflyTable {
     }
 }
 
+// NaN logits or an underflowed total leave the softmax ill-defined,
+// in which case expansion falls back to a uniform policy
+fn is_degenerate_softmax(total: f32) -> bool {
+    !total.is_finite() || total <= 0.0
+}
+
 fn scale_bonus(score: i16, bonus: i32, reduction_factor: i32) -> i16 {
     let bonus = bonus.clamp(i16::MIN as i32, i16::MAX as i32);
     let reduction_factor = reduction_factor.max(1);
@@ -293,6 +302,7 @@ pub struct Tree {
     root: ChessState,
     tree: [TreeHalf; 2],
     half: AtomicBool,
+    flips: AtomicUsize,
     hash: HashTable,
     butterfly: ButterflyTable,
     root_accumulator: RootAccumulator,
@@ -306,6 +316,23 @@ impl Index<NodePtr> for Tree {
     }
 }
 
+/// Total system memory in megabytes, if the platform reports it.
+fn total_memory_mb() -> Option<usize> {
+    if cfg!(target_os = "linux") {
+        let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+        parse_meminfo_total_mb(&meminfo)
+    } else {
+        None
+    }
+}
+
+/// Reads the `MemTotal` line of a `/proc/meminfo` listing, given in kB.
+fn parse_meminfo_total_mb(meminfo: &str) -> Option<usize> {
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+    Some(kb / 1024)
+}
+
 impl Tree {
     pub fn new_mb(mb: usize, threads: usize) -> Self {
         let bytes = mb * 1024 * 1024;
@@ -320,6 +347,29 @@ impl Tree {
         Self::new(bytes / node_bytes, bytes / node_bytes / 16, threads)
     }
 
+    /// Tree using `fraction` of the system's total memory, clamped to the
+    /// range accepted by the `Hash` UCI option. Falls back to the default
+    /// hash size where total memory can't be determined.
+    pub fn new_auto(fraction: f32, threads: usize) -> Self {
+        let mb = total_memory_mb()
+            .map(|total| (total as f64 * f64::from(fraction.clamp(0.0, 1.0))) as usize)
+            .unwrap_or(DEFAULT_AUTO_MB);
+
+        Self::new_mb(mb.clamp(MIN_AUTO_MB, MAX_AUTO_MB), threads)
+    }
+
+    /// Tree holding exactly `node_count` nodes, split evenly between the two
+    /// halves, with `hash_entries` hash table entries. Useful where the point
+    /// at which the tree fills up needs to be known exactly.
+    ///
+    /// #### Note
+    /// An odd `node_count` is rounded down, as each half gets half of it.
+    /// Each half holds at least one node and the hash table at least one
+    /// entry, so small or zero counts are rounded up rather than panicking.
+    pub fn new_nodes(node_count: usize, hash_entries: usize, threads: usize) -> Self {
+        Self::new(node_count.max(2), hash_entries.max(1) * 4, threads.max(1))
+    }
+
     fn new(tree_cap: usize, hash_cap: usize, threads: usize) -> Self {
         let tree = Self {
             root: ChessState::default(),
@@ -328,6 +378,7 @@ impl Tree {
                 TreeHalf::new(tree_cap / 2, true, threads),
             ],
             half: AtomicBool::new(false),
+            flips: AtomicUsize::new(0),
             hash: HashTable::new(hash_cap / 4, threads),
             butterfly: ButterflyTable::new(),
             root_accumulator: RootAccumulator::new(threads),
@@ -363,6 +414,20 @@ impl Tree {
         self.tree[self.half()].is_full()
     }
 
+    /// Nodes in use across both halves, in permille of their total capacity.
+    pub fn fill_permille(&self) -> usize {
+        let used = self.tree[0].used() + self.tree[1].used();
+        let capacity = self.tree[0].capacity() + self.tree[1].capacity();
+
+        (used * 1000).checked_div(capacity).unwrap_or(0).min(1000)
+    }
+
+    /// Whether a half can hold a root with `root_moves` children and
+    /// still leave room for every thread to expand below it.
+    pub fn can_search(&self, root_moves: usize, threads: usize) -> bool {
+        self.tree[0].capacity() >= TreeHalf::min_capacity(root_moves, threads)
+    }
+
     pub fn push_new_node(&self) -> Option<NodePtr> {
         self.tree[self.half()].reserve_nodes_thread(1, 0)
     }
@@ -400,6 +465,7 @@ impl Tree {
 
     pub fn flip(&self, copy_across: bool) {
         let old_root_ptr = self.root_node();
+        self.flips.fetch_add(1, Ordering::Relaxed);
 
         self.root_accumulator
             .flush_all(|ptr, delta| self[ptr].apply_delta(delta));
@@ -418,6 +484,11 @@ impl Tree {
         self.reset_root_accumulator();
     }
 
+    /// Number of flips since the tree was last cleared.
+    pub fn flips(&self) -> usize {
+        self.flips.load(Ordering::Relaxed)
+    }
+
     #[must_use]
     pub fn fetch_children(&self, parent_ptr: NodePtr, thread_id: usize) -> Option<()> {
         let first_child_ptr = { self[parent_ptr].actions() };
@@ -477,11 +548,23 @@ impl Tree {
     pub fn clear(&mut self, threads: usize) {
         self.root = ChessState::default();
         self.clear_halves();
+        self.flips.store(0, Ordering::Relaxed);
         self.hash.clear(threads);
         self.butterfly.clear();
         self.root_accumulator.reset(self.root_node());
     }
 
+    /// As [`Tree::clear`], but keeps the hash table contents, starting a
+    /// new generation so that older entries are the first replaced.
+    pub fn clear_keep_tt(&mut self) {
+        self.root = ChessState::default();
+        self.clear_halves();
+        self.flips.store(0, Ordering::Relaxed);
+        self.hash.new_generation();
+        self.butterfly.clear();
+        self.root_accumulator.reset(self.root_node());
+    }
+
     pub fn is_empty(&self) -> bool {
         self.tree[0].is_empty() && self.tree[1].is_empty()
     }
@@ -518,8 +601,6 @@ impl Tree {
             max = max.max(adjusted);
         });
 
-        let new_ptr = self.tree[self.half()].reserve_nodes_thread(count, thread_id)?;
-
         let pst = SearchHelpers::get_pst(depth, self[node_ptr].q(), params);
 
         let slice = unsafe {
@@ -532,8 +613,38 @@ impl Tree {
             total += *policy;
         }
 
+        if is_degenerate_softmax(total) {
+            slice.iter_mut().for_each(|(_, policy)| *policy = 1.0);
+            total = count as f32;
+        }
+
         slice.sort_unstable_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
 
+        // skip children with negligible policy, keeping at least the moves
+        // PUCT considers anyway, and fold their mass into the kept ones
+        let threshold = params.expand_policy_threshold() * total;
+        let min_actions = if depth == 1 {
+            params.root_min_policy_actions()
+        } else {
+            params.min_policy_actions()
+        };
+        let min_kept = (min_actions.max(1) as usize).min(count);
+        let kept = slice
+            .iter()
+            .position(|&(_, policy)| policy < threshold)
+            .unwrap_or(count)
+            .max(min_kept);
+
+        if kept < count {
+            total = slice[..kept].iter().map(|&(_, policy)| policy).sum();
+        }
+
+        let pruned = kept < count;
+        let slice = &slice[..kept];
+        let count = kept;
+
+        let new_ptr = self.tree[self.half()].reserve_nodes_thread(count, thread_id)?;
+
         let mut sum_of_squares = 0.0;
 
         for (action, (mov, policy)) in slice.iter().enumerate() {
@@ -549,6 +660,7 @@ impl Tree {
 
         actions_ptr.store(new_ptr);
         node.set_num_actions(count);
+        node.set_pruned(pruned);
         self.tree[self.half()].register_cross_link(node_ptr, new_ptr);
 
         Some(())
@@ -589,6 +701,11 @@ impl Tree {
             total += *policy;
         }
 
+        if is_degenerate_softmax(total) {
+            policies.iter_mut().for_each(|policy| *policy = 1.0);
+            total = num_actions as f32;
+        }
+
         let mut sum_of_squares = 0.0;
 
         for (action, &policy) in policies.iter().enumerate() {
@@ -611,14 +728,28 @@ impl Tree {
 
     pub fn propogate_proven_mates(&self, ptr: NodePtr, child_state: GameState) {
         match child_state {
-            // if the child node resulted in a loss, then
-            // this node has a guaranteed win
-            GameState::Lost(n) => self[ptr].set_state(GameState::Won(n + 1)),
+            // if the child node resulted in a loss, then this node has a
+            // guaranteed win, taking the fastest mate among lost children
+            GameState::Lost(n) => {
+                let mut min_loss_len = n;
+
+                if let GameState::Won(m) = self[ptr].state() {
+                    min_loss_len = min_loss_len.min(m.saturating_sub(1));
+                }
+
+                self[ptr].set_state(GameState::Won(min_loss_len.saturating_add(1)));
+            }
             // if the child node resulted in a win, then check if there are
-            // any non-won children, and if not, guaranteed loss for this node
+            // any non-won children, and if not, guaranteed loss for this node,
+            // delayed for as long as possible
             GameState::Won(n) => {
                 assert_ne!(self[ptr].num_actions(), 0);
 
+                // a skipped move may still escape
+                if self[ptr].is_pruned() {
+                    return;
+                }
+
                 let mut proven_loss = true;
                 let mut max_win_len = n;
                 let first_child_ptr = self[ptr].actions();
@@ -635,7 +766,7 @@ impl Tree {
                 }
 
                 if proven_loss {
-                    self[ptr].set_state(GameState::Lost(max_win_len + 1));
+                    self[ptr].set_state(GameState::Lost(max_win_len.saturating_add(1)));
                 }
             }
             // nothing to do otherwise
@@ -674,6 +805,7 @@ impl Tree {
         if !found {
             println!("info string no subtree found");
             self.clear_halves();
+            self.hash.new_generation();
         }
     }
 
@@ -716,6 +848,46 @@ impl Tree {
         NodePtr::NULL
     }
 
+    /// Visit counts of every root child, in the order they are stored.
+    /// Legal moves skipped by `expand_policy_threshold` follow with zero
+    /// visits, so every legal move is always listed.
+    pub fn root_distribution(&self) -> Vec<(Move, u32)> {
+        let root = &self[self.root_node()];
+        let actions = root.actions();
+
+        let mut dist: Vec<(Move, u32)> = (0..root.num_actions())
+            .map(|action| {
+                let node = &self[actions + action];
+                let visits = node.visits().min(u64::from(u32::MAX)) as u32;
+                (node.parent_move(), visits)
+            })
+            .collect();
+
+        if root.is_pruned() {
+            let kept = dist.len();
+            self.root.map_legal_moves(|mov| {
+                if !dist[..kept].iter().any(|&(kept_mov, _)| kept_mov == mov) {
+                    dist.push((mov, 0));
+                }
+            });
+        }
+
+        dist
+    }
+
+    /// Shannon entropy (in nats) of the policy over a node's children,
+    /// the log-scale counterpart to its gini impurity.
+    pub fn policy_entropy(&self, ptr: NodePtr) -> f32 {
+        let node = &self[ptr];
+        let actions = node.actions();
+
+        (0..node.num_actions())
+            .map(|action| self[actions + action].policy())
+            .filter(|&policy| policy > 0.0)
+            .map(|policy| -policy * policy.ln())
+            .sum()
+    }
+
     pub fn get_best_child(&self, ptr: NodePtr) -> usize {
         self.get_best_child_by_key(ptr, |n| n.visits() as f32)
     }
@@ -787,8 +959,15 @@ impl Tree {
         self[child_ptr + (node.num_actions() - 1)].parent_move()
     }
 
-    #[cfg(feature = "datagen")]
-    pub fn add_dirichlet_noise_to_node(&self, ptr: NodePtr, alpha: f32, prop: f32) {
+    /// Mixes symmetric dirichlet noise with concentration `alpha` into the
+    /// children's policies, in proportion `prop`, drawing from `rng`.
+    pub fn add_dirichlet_noise_to_node(
+        &self,
+        ptr: NodePtr,
+        alpha: f32,
+        prop: f32,
+        rng: &mut impl rand::Rng,
+    ) {
         use rand_distr::{Distribution, Gamma};
 
         let node = &self[ptr];
@@ -799,7 +978,6 @@ impl Tree {
 
         let actions_ptr = node.actions();
 
-        let mut rng = rand::rng();
         let k = node.num_actions();
 
         // Symmetric Dirichlet via Gamma(alpha, 1) samples
@@ -807,7 +985,7 @@ impl Tree {
         let mut sum = 0.0;
         let mut noise = Vec::with_capacity(k);
         for _ in 0..k {
-            let x = gamma.sample(&mut rng);
+            let x = gamma.sample(rng);
             sum += x;
             noise.push(x);
         }
@@ -821,3 +999,238 @@ impl Tree {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks;
+
+    fn expanded_root(fen: &str, params: &MctsParams) -> Tree {
+        expanded_root_with(fen, params, networks::zeroed().0)
+    }
+
+    fn expanded_root_with(fen: &str, params: &MctsParams, policy: &PolicyNetwork) -> Tree {
+        let mut tree = Tree::new_nodes(1 << 12, 1 << 8, 1);
+        let pos = ChessState::from_fen(fen);
+        tree.set_root_position(&pos);
+
+        let ptr = tree.push_new_node().unwrap();
+        tree[ptr].clear();
+
+        tree.expand_node(ptr, &pos, params, policy, 1, 0).unwrap();
+
+        tree
+    }
+
+    fn root_policies(tree: &Tree) -> Vec<f32> {
+        let root = tree.root_node();
+        let first = tree[root].actions();
+
+        (0..tree[root].num_actions())
+            .map(|action| tree[first + action].policy())
+            .collect()
+    }
+
+    #[test]
+    fn degenerate_logits_are_detected() {
+        let total = |logits: &[f32]| -> f32 {
+            let max = logits.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+            logits.iter().map(|&logit| (logit - max).exp()).sum()
+        };
+
+        assert!(is_degenerate_softmax(total(&[f32::NAN, 0.0, 1.0])));
+        assert!(is_degenerate_softmax(total(&[f32::INFINITY, 0.0])));
+        assert!(is_degenerate_softmax(total(&[])));
+        assert!(!is_degenerate_softmax(total(&[0.0, 0.0, 0.0])));
+        assert!(!is_degenerate_softmax(total(&[-3.0, 1.0, 2.0])));
+    }
+
+    #[test]
+    fn equal_logits_expand_to_a_uniform_policy() {
+        let tree = expanded_root(ChessState::STARTPOS, &MctsParams::default());
+        let policies = root_policies(&tree);
+
+        assert_eq!(policies.len(), 20);
+        for policy in policies {
+            assert!((policy - 1.0 / 20.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn single_move_gets_all_the_policy() {
+        // Kb1 is the only legal move
+        let tree = expanded_root("7k/8/8/8/8/8/2r5/K7 w - - 0 1", &MctsParams::default());
+        let policies = root_policies(&tree);
+
+        assert_eq!(policies.len(), 1);
+        assert!((policies[0] - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn dirichlet_noise_keeps_the_policy_normalised() {
+        let tree = expanded_root(ChessState::STARTPOS, &MctsParams::default());
+        let clean = root_policies(&tree);
+
+        tree.add_dirichlet_noise_to_node(tree.root_node(), 0.3, 0.25, &mut rand::rng());
+        let noisy = root_policies(&tree);
+
+        let sum: f32 = noisy.iter().sum();
+        assert!((sum - 1.0).abs() < 1e-3);
+        assert_ne!(clean, noisy);
+    }
+
+    #[test]
+    fn proven_mates_keep_their_distance() {
+        let tree = expanded_root(ChessState::STARTPOS, &MctsParams::default());
+        let root = tree.root_node();
+        let first = tree[root].actions();
+
+        // a win takes the fastest mate among the lost children,
+        // whatever order they are proven in
+        for (action, len) in [(3, 5), (7, 1), (11, 3)] {
+            tree[first + action].set_state(GameState::Lost(len));
+            tree.propogate_proven_mates(root, GameState::Lost(len));
+        }
+
+        assert_eq!(tree[root].state(), GameState::Won(2));
+    }
+
+    #[test]
+    fn proven_losses_take_the_slowest_mate() {
+        let tree = expanded_root(ChessState::STARTPOS, &MctsParams::default());
+        let root = tree.root_node();
+        let first = tree[root].actions();
+        let num_actions = tree[root].num_actions();
+
+        for action in 0..num_actions - 1 {
+            let len = (action % 4) as u8;
+            tree[first + action].set_state(GameState::Won(len));
+            tree.propogate_proven_mates(root, GameState::Won(len));
+        }
+
+        // one move still escapes
+        assert_eq!(tree[root].state(), GameState::Ongoing);
+
+        tree[first + (num_actions - 1)].set_state(GameState::Won(6));
+        tree.propogate_proven_mates(root, GameState::Won(6));
+        assert_eq!(tree[root].state(), GameState::Lost(7));
+    }
+
+    #[test]
+    fn new_nodes_fills_to_exactly_its_capacity_and_flips() {
+        const HALF: usize = 2048;
+
+        let tree = Tree::new_nodes(2 * HALF, 16, 1);
+        assert!(tree.is_empty());
+
+        for i in 0..HALF {
+            assert!(tree.push_new_node().is_some(), "no room for node {i}");
+        }
+
+        assert!(tree.is_full());
+        assert_eq!(tree.fill_permille(), 500);
+        assert!(tree.push_new_node().is_none());
+
+        let root = tree.root_node();
+        tree[root].update(0.75, 0.0);
+
+        tree.flip(true);
+        assert_eq!(tree.flips(), 1);
+        assert_ne!(tree.root_node().half(), root.half());
+
+        // the root is carried across, and the rest of the half is free again
+        let root = tree.root_node();
+        assert_eq!(tree[root].visits(), 1);
+        assert!((tree[root].q() - 0.75).abs() < 1e-3);
+        assert!(!tree.is_full());
+
+        for i in 1..HALF {
+            assert!(tree.push_new_node().is_some(), "no room for node {i}");
+        }
+
+        assert!(tree.is_full());
+        assert!(tree.push_new_node().is_none());
+    }
+
+    #[test]
+    fn root_policy_temperature_flattens_the_root_policy() {
+        let policy = PolicyNetwork::random_outputs(0x523);
+
+        let gini = |temperature: i32| {
+            let mut params = MctsParams::default();
+            params.set("root_policy_temperature", temperature);
+
+            let tree = expanded_root_with(ChessState::STARTPOS, &params, &policy);
+            tree[tree.root_node()].gini_impurity()
+        };
+
+        // a flatter policy is less concentrated, so has a higher impurity
+        let sharp = gini(250);
+        let neutral = gini(1000);
+        let flat = gini(4000);
+
+        assert!(sharp < neutral, "{sharp} >= {neutral}");
+        assert!(neutral < flat, "{neutral} >= {flat}");
+    }
+
+    #[test]
+    fn dominant_move_has_lower_gini_and_entropy_than_an_open_position() {
+        let params = MctsParams::default();
+        let state = ChessState::default();
+
+        let mut e4 = Move::NULL;
+        state.map_legal_moves(|mov| {
+            if state.conv_mov_to_str(mov) == "e2e4" {
+                e4 = mov;
+            }
+        });
+
+        let dominant = PolicyNetwork::favouring(&state.board(), e4);
+
+        let open = expanded_root(ChessState::STARTPOS, &params);
+        let sharp = expanded_root_with(ChessState::STARTPOS, &params, &dominant);
+
+        let (open_gini, sharp_gini) = (
+            open[open.root_node()].gini_impurity(),
+            sharp[sharp.root_node()].gini_impurity(),
+        );
+        let (open_entropy, sharp_entropy) = (
+            open.policy_entropy(open.root_node()),
+            sharp.policy_entropy(sharp.root_node()),
+        );
+
+        assert!(sharp_gini < open_gini, "{sharp_gini} >= {open_gini}");
+        assert!(
+            sharp_entropy < open_entropy,
+            "{sharp_entropy} >= {open_entropy}"
+        );
+
+        // a uniform policy over the 20 opening moves
+        assert!((open_gini - 0.95).abs() < 5e-3, "{open_gini}");
+        assert!((open_entropy - 20f32.ln()).abs() < 1e-2, "{open_entropy}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn meminfo_total_is_read_in_megabytes() {
+        let meminfo = "MemTotal:       16314520 kB\n\
+                       MemFree:         1219748 kB\n\
+                       MemAvailable:    9727036 kB\n";
+
+        assert_eq!(parse_meminfo_total_mb(meminfo), Some(15932));
+        assert_eq!(parse_meminfo_total_mb("MemFree: 1219748 kB\n"), None);
+        assert_eq!(parse_meminfo_total_mb("MemTotal: lots kB\n"), None);
+        assert_eq!(parse_meminfo_total_mb(""), None);
+    }
+
+    #[test]
+    fn auto_sizing_never_panics() {
+        // whether or not total memory can be read here
+        let _ = total_memory_mb();
+
+        for fraction in [0.0, -1.0, f32::NAN, 1e-9] {
+            let tree = Tree::new_auto(fraction, 1);
+            assert!(tree.is_empty());
+        }
+    }
+}
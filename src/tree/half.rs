@@ -142,6 +142,16 @@ impl TreeHalf {
         self.used.load(Ordering::Relaxed) == 0
     }
 
+    pub fn capacity(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Smallest capacity that fits the root and its `children`, plus one
+    /// reservation block per thread for the plies below them.
+    pub fn min_capacity(children: usize, threads: usize) -> usize {
+        1 + children + threads * CACHE_SIZE.max(children)
+    }
+
     pub fn used(&self) -> usize {
         self.used.load(Ordering::Relaxed)
     }
@@ -25,6 +25,7 @@ struct HashEntryInternal {
     q: AtomicU32,
     d: AtomicU32,
     visits: AtomicU32,
+    gen: AtomicU32,
 }
 
 impl Clone for HashEntryInternal {
@@ -34,17 +35,25 @@ impl Clone for HashEntryInternal {
             q: AtomicU32::new(self.q.load(Ordering::Relaxed)),
             d: AtomicU32::new(self.d.load(Ordering::Relaxed)),
             visits: AtomicU32::new(self.visits.load(Ordering::Relaxed)),
+            gen: AtomicU32::new(self.gen.load(Ordering::Relaxed)),
         }
     }
 }
 
+/// Entries written before the last [`HashTable::new_generation`] stay
+/// retrievable, but are replaced by any current entry regardless of
+/// visits, so they age out as the new search fills the table.
 pub struct HashTable {
     table: Vec<HashEntryInternal>,
+    gen: u32,
 }
 
 impl HashTable {
     pub fn new(size: usize, _threads: usize) -> Self {
-        let mut table = HashTable { table: Vec::new() };
+        let mut table = HashTable {
+            table: Vec::new(),
+            gen: 0,
+        };
         table.table.resize_with(size, HashEntryInternal::default);
 
         table
@@ -64,6 +73,10 @@ impl HashTable {
         });
     }
 
+    pub fn new_generation(&mut self) {
+        self.gen = self.gen.wrapping_add(1);
+    }
+
     pub fn fetch(&self, hash: u64) -> HashEntry {
         let idx = hash % (self.table.len() as u64);
         let entry = &self.table[idx as usize];
@@ -99,7 +112,7 @@ impl HashTable {
 
         let entry = &self.table[idx as usize];
         let existing_hash = entry.hash.load(Ordering::Relaxed);
-        let replace = if existing_hash != key {
+        let replace = if existing_hash != key || entry.gen.load(Ordering::Relaxed) != self.gen {
             true
         } else {
             visits_u32 >= entry.visits.load(Ordering::Relaxed)
@@ -109,6 +122,7 @@ impl HashTable {
             entry.q.store(q_u32, Ordering::Relaxed);
             entry.d.store(d_u32, Ordering::Relaxed);
             entry.visits.store(visits_u32, Ordering::Relaxed);
+            entry.gen.store(self.gen, Ordering::Relaxed);
             entry.hash.store(key, Ordering::Relaxed);
         }
     }
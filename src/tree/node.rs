@@ -1,7 +1,7 @@
 use std::{
     convert::TryFrom,
     ops::{Add, AddAssign},
-    sync::atomic::{AtomicU16, AtomicU64, AtomicU8, Ordering},
+    sync::atomic::{AtomicBool, AtomicU16, AtomicU64, AtomicU8, Ordering},
 };
 
 use crate::chess::{GameState, Move};
@@ -104,6 +104,7 @@ pub struct Node {
     sum_sq_q: AtomicU64,
     draws: AtomicU64,
     gini_impurity: AtomicU8,
+    pruned: AtomicBool,
 }
 
 impl Node {
@@ -120,6 +121,7 @@ impl Node {
             sum_sq_q: AtomicU64::new(0),
             draws: AtomicU64::new(0),
             gini_impurity: AtomicU8::new(0),
+            pruned: AtomicBool::new(false),
         }
     }
 
@@ -239,9 +241,20 @@ impl Node {
         );
     }
 
+    /// Whether expansion skipped some legal moves, in which case the
+    /// children present cannot prove a loss on their own.
+    pub fn is_pruned(&self) -> bool {
+        self.pruned.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pruned(&self, pruned: bool) {
+        self.pruned.store(pruned, Ordering::Relaxed);
+    }
+
     pub fn clear_actions(&self) {
         self.actions.write().store(NodePtr::NULL);
         self.num_actions.store(0, Ordering::Relaxed);
+        self.set_pruned(false);
     }
 
     pub fn parent_move(&self) -> Move {
@@ -257,6 +270,7 @@ impl Node {
         self.state.store(other.state.load(Relaxed), Relaxed);
         self.gini_impurity
             .store(other.gini_impurity.load(Relaxed), Relaxed);
+        self.pruned.store(other.pruned.load(Relaxed), Relaxed);
         self.visits.store(other.visits.load(Relaxed), Relaxed);
         self.sum_q.store(other.sum_q.load(Relaxed), Relaxed);
         self.sum_sq_q.store(other.sum_sq_q.load(Relaxed), Relaxed);
@@ -300,7 +314,6 @@ impl Node {
         }
     }
 
-    #[cfg(feature = "datagen")]
     pub fn kld_gain(new_visit_dist: &[i32], old_visit_dist: &[i32]) -> Option<f64> {
         let new_parent_visits = new_visit_dist.iter().sum::<i32>();
         let old_parent_visits = old_visit_dist.iter().sum::<i32>();
@@ -1,24 +1,63 @@
 use crate::{
-    chess::{ChessState, Move},
-    mcts::{Limits, MctsParams, SearchHelpers, Searcher, REPORT_ITERS},
-    networks::{PolicyNetwork, ValueNetwork},
+    chess::{ChessState, GameState, Move},
+    mcts::{
+        Limits, MctsParams, Score, SearchHelpers, SearchInfo, Searcher, REPORT_ITERS,
+        SHOW_CURRLINE, SHOW_WDL,
+    },
+    network_name_hash,
+    networks::{EvalCache, PolicyNetwork, ValueNetwork},
     tree::Tree,
+    try_read_into_struct, try_read_into_struct_verified, MappedWeights,
 };
 
+use montyformat::{MontyFormat, SearchData};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
 use std::{
-    io, process,
+    fs::File,
+    io::{self, BufRead, BufWriter, Write},
+    process,
     sync::atomic::{AtomicBool, Ordering},
     time::Instant,
 };
 
+/// Dirichlet noise mixed into the root policy at the start of each search,
+/// so repeated analysis of a position explores different lines.
+#[derive(Clone, Copy)]
+struct AnalysisNoise {
+    enabled: bool,
+    alpha: f32,
+    epsilon: f32,
+}
+
+impl Default for AnalysisNoise {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            alpha: 0.3,
+            epsilon: 0.25,
+        }
+    }
+}
+
+impl AnalysisNoise {
+    fn get(&self) -> Option<(f32, f32)> {
+        self.enabled.then_some((self.alpha, self.epsilon))
+    }
+}
+
 pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
     let mut pos = ChessState::default();
     let mut root_game_ply = 0;
     let mut params = MctsParams::default();
     let mut hash_mb = 64;
     let mut tree = Tree::new_mb(hash_mb, 1);
+    let mut eval_cache = EvalCache::new_mb(16);
     let mut report_moves = false;
+    let mut debug = false;
     let mut threads = 1;
+    let mut clear_threads = available_threads();
     let mut move_overhead = 400;
     let mut multipv = 1usize;
     let mut gui_compatibility = true;
@@ -26,6 +65,11 @@ pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
     let mut uci_rating_adv: Option<i32> = None;
     let mut contempt_override: Option<i32> = None;
     let mut contempt_analysis = false;
+    let mut stop_on_score: Option<i32> = None;
+    let mut analysis_noise = AnalysisNoise::default();
+    let mut mate_search = false;
+    let mut policy_file: Option<MappedWeights<PolicyNetwork>> = None;
+    let mut value_file: Option<MappedWeights<ValueNetwork>> = None;
 
     let mut stored_message: Option<String> = None;
 
@@ -47,6 +91,10 @@ pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
 
         let commands = input.split_whitespace().collect::<Vec<_>>();
 
+        // networks loaded through `PolicyFile`/`EvalFile` take precedence
+        let policy = policy_file.as_ref().map_or(policy, MappedWeights::get);
+        let value = value_file.as_ref().map_or(value, MappedWeights::get);
+
         let cmd = *commands.first().unwrap_or(&"oops");
         match cmd {
             "isready" => println!("readyok"),
@@ -56,6 +104,7 @@ pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
                 &mut report_moves,
                 &mut tree,
                 &mut threads,
+                &mut clear_threads,
                 &mut move_overhead,
                 &mut hash_mb,
                 &mut multipv,
@@ -64,8 +113,15 @@ pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
                 &mut uci_rating_adv,
                 &mut contempt_override,
                 &mut contempt_analysis,
+                &mut stop_on_score,
+                &mut eval_cache,
+                &mut analysis_noise,
+                &mut mate_search,
+                &mut policy_file,
+                &mut value_file,
             ),
             "position" => position(commands, &mut pos),
+            "debug" => debug = commands.get(1) == Some(&"on"),
             "go" => {
                 // increment game ply every time `go` is called
                 root_game_ply += 2;
@@ -73,17 +129,23 @@ pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
                 go(
                     &commands,
                     &mut tree,
+                    &eval_cache,
                     &pos,
                     root_game_ply,
                     &params,
                     report_moves,
+                    debug,
                     multipv,
                     policy,
                     value,
                     threads,
+                    clear_threads,
                     move_overhead,
                     gui_compatibility,
                     contempt_analysis,
+                    stop_on_score,
+                    analysis_noise.get(),
+                    mate_search,
                     &mut stored_message,
                     #[cfg(feature = "datagen")]
                     1.0,
@@ -99,9 +161,11 @@ pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
                 bench(depth, policy, value, &params);
             }
             "perft" => run_perft(&commands, &pos),
+            "nodes" => display_root_nodes(&tree, &params),
+            "selfplay" => selfplay(&commands, policy, value, &params, hash_mb, threads),
             "quit" => std::process::exit(0),
             "eval" => {
-                let breakdown = pos.eval_with_contempt(value, &params, pos.stm());
+                let breakdown = pos.eval_with_contempt(value, &params, pos.stm(), None);
                 println!("cp: {}", breakdown.cp);
                 println!(
                     "wdl raw: {:.2}% {:.2}% {:.2}%",
@@ -151,21 +215,29 @@ pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
                 }
             }
             "d" => pos.display(policy),
+            "deval" => pos.display_eval(value, &params),
             "params" => params.list_spsa(),
             "uci" => preamble(tcec_mode),
             "ucinewgame" => {
                 root_game_ply = 0;
-                tree.clear(threads);
+                tree.clear(clear_threads);
             }
             _ => {}
         }
     }
 }
 
+/// Searches a fixed suite of positions single-threaded and prints the
+/// total nodes and nps, preceded by a signature of the best moves found.
+/// Both the node count and signature are the same on every run with the
+/// same networks, so a change in either flags a change in search.
 pub fn bench(depth: usize, policy: &PolicyNetwork, value: &ValueNetwork, params: &MctsParams) {
     let mut total_nodes = 0;
     let mut time = 0.0;
 
+    // FNV-1a over the best moves, in suite order
+    let mut signature: u64 = 0xCBF2_9CE4_8422_2325;
+
     let bench_fens = [
         "r3k2r/2pb1ppp/2pp1q2/p7/1nP1B3/1P2P3/P2N1PPP/R2QK2R w KQkq a6 0 14",
         "4rrk1/2p1b1p1/p1p3q1/4p3/2P2n1p/1P1NR2P/PB3PP1/3R1QK1 b - - 2 24",
@@ -228,8 +300,9 @@ pub fn bench(depth: usize, policy: &PolicyNetwork, value: &ValueNetwork, params:
         opt_time: None,
         max_depth: depth,
         max_nodes: 1_000_000,
-        #[cfg(feature = "datagen")]
         kld_min_gain: None,
+        stop_score: None,
+        mate: None,
     };
 
     let mut tree = Tree::new_mb(32, 1);
@@ -241,29 +314,334 @@ pub fn bench(depth: usize, policy: &PolicyNetwork, value: &ValueNetwork, params:
         let searcher = Searcher::new(&tree, params, policy, value, &abort);
         let timer = Instant::now();
         #[cfg(not(feature = "datagen"))]
-        searcher.search(1, limits, false, 1, false, &mut total_nodes);
+        let (mov, _) = searcher.search(1, limits, false, 1, false, &mut total_nodes, None);
         #[cfg(feature = "datagen")]
-        searcher.search(1, limits, false, 1, false, &mut total_nodes, false, 1.0);
+        let (mov, _, _) = searcher.search(1, limits, false, 1, false, &mut total_nodes, None, 1.0);
         time += timer.elapsed().as_secs_f32();
         tree.clear(1);
+
+        for byte in u16::from(mov).to_le_bytes() {
+            signature = (signature ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3);
+        }
     }
 
+    println!("Signature: {signature:016x}");
     println!(
         "Bench: {total_nodes} nodes {:.0} nps",
         total_nodes as f32 / time
     );
 }
 
+/// `analyze-stream [nodes <n>] [movetime <ms>] [threads <t>] [hash <mb>]`
+///
+/// Reads FENs from stdin, one per line, and searches each in turn,
+/// writing `fen,bestmove,score,wdl` to stdout as each search finishes.
+/// The score is `cp:<x>` or `mate:<y>` and the wdl is out of 1000, both for
+/// the side to move. A line that isn't a valid FEN gets the row
+/// `fen,0000,error,-`, with the reason on stderr, and the stream carries on.
+/// The tree is cleared between positions but the hash table is kept, so
+/// consecutive related positions still share work.
+pub fn analyze_stream(
+    commands: &[&str],
+    policy: &PolicyNetwork,
+    value: &ValueNetwork,
+    params: &MctsParams,
+) {
+    let mut nodes = 10_000;
+    let mut movetime = None;
+    let mut threads = 1;
+    let mut hash_mb = 64;
+
+    let mut mode = "";
+
+    for cmd in commands {
+        match *cmd {
+            "nodes" => mode = "nodes",
+            "movetime" => mode = "movetime",
+            "threads" => mode = "threads",
+            "hash" => mode = "hash",
+            _ => match mode {
+                "nodes" => nodes = cmd.parse().unwrap_or(nodes),
+                "movetime" => movetime = cmd.parse().ok(),
+                "threads" => threads = cmd.parse().unwrap_or(threads).max(1),
+                "hash" => hash_mb = cmd.parse().unwrap_or(hash_mb).max(1),
+                _ => mode = "none",
+            },
+        }
+    }
+
+    let limits = Limits {
+        max_time: movetime,
+        opt_time: None,
+        max_depth: 256,
+        max_nodes: nodes,
+        kld_min_gain: None,
+        stop_score: None,
+        mate: None,
+    };
+
+    let mut tree = Tree::new_mb(hash_mb, threads);
+    let mut stdout = io::stdout();
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+
+        let fen = line.trim();
+
+        if fen.is_empty() {
+            continue;
+        }
+
+        let pos = match ChessState::try_from_fen(fen) {
+            Ok(pos) => pos,
+            Err(err) => {
+                eprintln!("error: {fen}: {err}");
+
+                if writeln!(stdout, "{fen},0000,error,-").is_err() {
+                    break;
+                }
+
+                continue;
+            }
+        };
+
+        let (mov, score, wdl) = match pos.game_state() {
+            GameState::Ongoing => {
+                tree.clear_keep_tt();
+                tree.set_root_position(&pos);
+
+                let abort = AtomicBool::new(false);
+                let mut last = None;
+
+                let mov = {
+                    let mut record = |info: SearchInfo| {
+                        if info.multipv == 1 {
+                            last = Some(info);
+                        }
+                    };
+
+                    let searcher = Searcher::new(&tree, params, policy, value, &abort)
+                        .with_info_callback(&mut record);
+
+                    #[cfg(not(feature = "datagen"))]
+                    let (mov, _) = searcher.search(threads, limits, false, 1, false, &mut 0, None);
+                    #[cfg(feature = "datagen")]
+                    let (mov, _, _) =
+                        searcher.search(threads, limits, false, 1, false, &mut 0, None, 0.0);
+
+                    mov
+                };
+
+                let (score, wdl) =
+                    last.map_or((Score::Cp(0), [0, 1000, 0]), |info| (info.score, info.wdl));
+
+                (pos.conv_mov_to_str(mov), score, wdl)
+            }
+            GameState::Lost(_) => ("0000".to_string(), Score::Mate(0), [0, 0, 1000]),
+            GameState::Won(_) => ("0000".to_string(), Score::Mate(0), [1000, 0, 0]),
+            GameState::Draw => ("0000".to_string(), Score::Cp(0), [0, 1000, 0]),
+        };
+
+        let score = match score {
+            Score::Cp(cp) => format!("cp:{cp}"),
+            Score::Mate(moves) => format!("mate:{moves}"),
+        };
+
+        let [win, draw, loss] = wdl;
+
+        if writeln!(stdout, "{fen},{mov},{score},{win} {draw} {loss}").is_err() {
+            break;
+        }
+    }
+}
+
+/// `selfplay [nodes <n>] [games <g>] [output <path>] [seed <s>]`
+/// `[noise_alpha <a>] [noise_epsilon <e>] [sampled_plies <p>]`
+///
+/// Plays games against itself with the full search at a fixed node budget
+/// and writes them, visit distributions included, in `MontyFormat`.
+///
+/// The search is deterministic, so each game is varied by mixing dirichlet
+/// noise (`noise_alpha`, `noise_epsilon` in thousandths) into its first root
+/// and by sampling the first `sampled_plies` moves by visits. Both draw from
+/// `seed`, which is chosen at random and printed when not given, so runs
+/// with one thread can be repeated.
+fn selfplay(
+    commands: &[&str],
+    policy: &PolicyNetwork,
+    value: &ValueNetwork,
+    params: &MctsParams,
+    hash_mb: usize,
+    threads: usize,
+) {
+    let mut nodes = 5000;
+    let mut games = 1;
+    let mut output = String::from("selfplay.binpack");
+    let mut seed = None;
+    let mut noise_alpha = 300;
+    let mut noise_epsilon = 250;
+    let mut sampled_plies = 8;
+
+    let mut mode = "";
+
+    for cmd in commands.iter().skip(1) {
+        match *cmd {
+            "nodes" => mode = "nodes",
+            "games" => mode = "games",
+            "output" => mode = "output",
+            "seed" => mode = "seed",
+            "noise_alpha" => mode = "noise_alpha",
+            "noise_epsilon" => mode = "noise_epsilon",
+            "sampled_plies" => mode = "sampled_plies",
+            _ => match mode {
+                "nodes" => nodes = cmd.parse().unwrap_or(nodes),
+                "games" => games = cmd.parse().unwrap_or(games),
+                "output" => output = cmd.to_string(),
+                "seed" => seed = cmd.parse().ok().or(seed),
+                "noise_alpha" => noise_alpha = cmd.parse().unwrap_or(noise_alpha),
+                "noise_epsilon" => noise_epsilon = cmd.parse().unwrap_or(noise_epsilon),
+                "sampled_plies" => sampled_plies = cmd.parse().unwrap_or(sampled_plies),
+                _ => mode = "none",
+            },
+        }
+    }
+
+    let seed = seed.unwrap_or_else(rand::random::<u64>);
+    println!("info string selfplay seed {seed}");
+
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // (alpha, epsilon) of the noise mixed into the first root of each game
+    let noise = (
+        noise_alpha.max(1) as f32 / 1000.0,
+        noise_epsilon.min(1000) as f32 / 1000.0,
+    );
+
+    let mut writer = match File::create(&output) {
+        Ok(file) => BufWriter::new(file),
+        Err(err) => {
+            println!("info string failed to create {output}: {err}");
+            return;
+        }
+    };
+
+    let limits = Limits {
+        max_time: None,
+        opt_time: None,
+        max_depth: 256,
+        max_nodes: nodes,
+        kld_min_gain: None,
+        stop_score: None,
+        mate: None,
+    };
+
+    let mut tree = Tree::new_mb(hash_mb, threads);
+    let mut buffer = Vec::new();
+
+    for game_idx in 1..=games {
+        let mut pos = ChessState::default();
+        let mut game = MontyFormat::new(pos.board(), pos.castling());
+
+        tree.clear(threads);
+
+        let mut ply = 0;
+
+        game.result = loop {
+            let abort = AtomicBool::new(false);
+            tree.set_root_position(&pos);
+            let searcher =
+                Searcher::new(&tree, params, policy, value, &abort).with_noise_seed(rng.random());
+
+            let root_noise = (ply == 0 && noise.1 > 0.0).then_some(noise);
+
+            #[cfg(not(feature = "datagen"))]
+            let (mov, score) =
+                searcher.search(threads, limits, false, 1, false, &mut 0, root_noise);
+            #[cfg(feature = "datagen")]
+            let (mov, score, _) =
+                searcher.search(threads, limits, false, 1, false, &mut 0, root_noise, 0.0);
+
+            // a sampled move is recorded with its own score, not the best move's
+            let (mov, score) = if ply < sampled_plies {
+                let sampled = searcher.select_move_with_temperature(1.0, &mut || rng.random());
+                (sampled, root_child_q(&tree, sampled).unwrap_or(score))
+            } else {
+                (mov, score)
+            };
+
+            ply += 1;
+
+            game.push(SearchData::new(mov, score, Some(tree.root_distribution())));
+            pos.make_move(mov);
+
+            // result is stored from white's perspective
+            match pos.game_state() {
+                GameState::Ongoing => {}
+                GameState::Draw => break 0.5,
+                GameState::Lost(_) => break if pos.stm() == 1 { 1.0 } else { 0.0 },
+                GameState::Won(_) => break if pos.stm() == 1 { 0.0 } else { 1.0 },
+            }
+        };
+
+        if let Err(err) = game
+            .serialise_into_buffer(&mut buffer)
+            .and_then(|_| writer.write_all(&buffer))
+        {
+            println!("info string failed to write game: {err}");
+            return;
+        }
+
+        buffer.clear();
+
+        println!(
+            "info string selfplay game {game_idx} plies {} result {}",
+            game.moves.len(),
+            game.result
+        );
+    }
+
+    if let Err(err) = writer.flush() {
+        println!("info string failed to write {output}: {err}");
+    }
+}
+
+/// Q of the root child reached by `mov`, from the root's perspective.
+fn root_child_q(tree: &Tree, mov: Move) -> Option<f32> {
+    let root = tree.root_node();
+    let first_child_ptr = tree[root].actions();
+
+    (0..tree[root].num_actions())
+        .map(|action| &tree[first_child_ptr + action])
+        .find(|child| child.parent_move() == mov)
+        .map(|child| child.q())
+}
+
 fn preamble(tcec_mode: bool) {
     println!("id name {}", env!("FORMATTED_NAME"));
     println!("id author Jamie Whiting, Viren & The Monty Authors");
     println!("option name Hash type spin default 64 min 1 max 524288");
     println!("option name Threads type spin default 1 min 1 max 512");
+    println!("option name ClearThreads type spin default 0 min 0 max 512");
+    println!("option name EvalCache type spin default 16 min 0 max 4096");
+    println!("option name EvalFile type string default <empty>");
+    println!("option name PolicyFile type string default <empty>");
+    println!("option name ParamsFile type string default <empty>");
     println!("option name UCI_Chess960 type check default false");
+    println!("option name Ponder type check default false");
     println!("option name Contempt_Analysis type check default false");
     println!("option name MoveOverhead type spin default 400 min 0 max 5000");
+    println!("option name TimeCheckInterval type spin default 0 min 0 max 65536");
     println!("option name MultiPV type spin default 1 min 1 max 10");
     println!("option name GUI_Compatibility type check default true");
+    println!("option name UCI_ShowWDL type check default false");
+    println!("option name UCI_ShowCurrLine type check default false");
+    println!("option name StopOnScore type spin default 0 min 0 max 10000");
+    println!("option name AnalysisNoise type check default false");
+    println!("option name MateSearch type check default false");
+    println!("option name AnalysisNoiseAlpha type spin default 300 min 1 max 10000");
+    println!("option name AnalysisNoiseEpsilon type spin default 250 min 0 max 1000");
     println!("option name report_moves type button");
     println!("option name report_iters type button");
     if tcec_mode {
@@ -271,6 +649,13 @@ fn preamble(tcec_mode: bool) {
         println!("option name UCI_RatingAdv type spin default 0");
     }
     println!("option name Contempt type spin default 0 min -1000 max 1000");
+    println!(
+        "option name ContemptPerspective type combo default SideToMove var SideToMove var White"
+    );
+    println!("option name DrawScore type spin default 500 min 0 max 1000");
+    println!("option name ScoreSmoothing type spin default 0 min 0 max 990");
+    println!("option name PolicyTemp type spin default 1000 min 100 max 10000");
+    println!("option name MoveSelectTemp type spin default 0 min 0 max 10000");
 
     #[cfg(feature = "tunable")]
     MctsParams::info(MctsParams::default());
@@ -285,6 +670,7 @@ fn setoption(
     report_moves: &mut bool,
     tree: &mut Tree,
     threads: &mut usize,
+    clear_threads: &mut usize,
     move_overhead: &mut usize,
     hash_mb: &mut usize,
     multipv: &mut usize,
@@ -293,6 +679,12 @@ fn setoption(
     uci_rating_adv: &mut Option<i32>,
     contempt_override: &mut Option<i32>,
     disable_tree_reuse: &mut bool,
+    stop_on_score: &mut Option<i32>,
+    eval_cache: &mut EvalCache,
+    analysis_noise: &mut AnalysisNoise,
+    mate_search: &mut bool,
+    policy_file: &mut Option<MappedWeights<PolicyNetwork>>,
+    value_file: &mut Option<MappedWeights<ValueNetwork>>,
 ) {
     let Some((name, value)) = parse_name_value(commands) else {
         return;
@@ -305,7 +697,25 @@ fn setoption(
         "report_iters" => {
             REPORT_ITERS.fetch_xor(true, Ordering::Relaxed);
         }
-        "UCI_Chess960" => {}
+        "UCI_Chess960" | "Ponder" => {}
+        "PolicyFile" => {
+            if load_network(policy_file, value.as_deref(), "policy") {
+                tree.clear(*threads);
+            }
+        }
+        "EvalFile" => {
+            if load_network(value_file, value.as_deref(), "value") {
+                tree.clear(*threads);
+                eval_cache.clear();
+            }
+        }
+        "ParamsFile" => match value.as_deref() {
+            None | Some("") | Some("<empty>") => {}
+            Some(path) => match params.load(path) {
+                Ok(()) => println!("info string loaded params from {path}"),
+                Err(err) => println!("info string failed to load params from {path}: {err}"),
+            },
+        },
         "Contempt_Analysis" => {
             if let Some(v) = value {
                 *disable_tree_reuse = v.eq_ignore_ascii_case("true");
@@ -320,6 +730,24 @@ fn setoption(
                 }
             }
         }
+        "ClearThreads" => {
+            if let Some(v) = value {
+                if let Ok(parsed) = v.parse::<usize>() {
+                    *clear_threads = if parsed == 0 {
+                        available_threads()
+                    } else {
+                        parsed
+                    };
+                }
+            }
+        }
+        "TimeCheckInterval" => {
+            if let Some(v) = value {
+                if let Ok(parsed) = v.parse::<i32>() {
+                    params.set("time_check_interval", parsed.clamp(0, 65536));
+                }
+            }
+        }
         "MoveOverhead" => {
             if let Some(v) = value {
                 if let Ok(parsed) = v.parse::<usize>() {
@@ -336,6 +764,39 @@ fn setoption(
                 }
             }
         }
+        "AnalysisNoise" => {
+            if let Some(v) = value {
+                analysis_noise.enabled = v.eq_ignore_ascii_case("true");
+            }
+        }
+        "AnalysisNoiseAlpha" => {
+            // given in thousandths
+            if let Some(v) = value {
+                if let Ok(parsed) = v.parse::<i32>() {
+                    analysis_noise.alpha = parsed.clamp(1, 10000) as f32 / 1000.0;
+                }
+            }
+        }
+        "AnalysisNoiseEpsilon" => {
+            // given in thousandths
+            if let Some(v) = value {
+                if let Ok(parsed) = v.parse::<i32>() {
+                    analysis_noise.epsilon = parsed.clamp(0, 1000) as f32 / 1000.0;
+                }
+            }
+        }
+        "MateSearch" => {
+            if let Some(v) = value {
+                *mate_search = v.eq_ignore_ascii_case("true");
+            }
+        }
+        "EvalCache" => {
+            if let Some(v) = value {
+                if let Ok(parsed) = v.parse::<usize>() {
+                    *eval_cache = EvalCache::new_mb(parsed);
+                }
+            }
+        }
         "MultiPV" => {
             if let Some(v) = value {
                 if let Ok(parsed) = v.parse::<usize>() {
@@ -348,6 +809,27 @@ fn setoption(
                 *gui_compatibility = v.eq_ignore_ascii_case("true");
             }
         }
+        "StopOnScore" => {
+            // threshold in reported centipawns, 0 disables. The best move must
+            // also have 4096 visits (STOP_SCORE_MIN_VISITS) and hold the score
+            // for 2 checks in a row, 4096 iterations apart
+            // (STOP_SCORE_STABLE_CHECKS), before the search stops
+            if let Some(v) = value {
+                if let Ok(parsed) = v.parse::<i32>() {
+                    *stop_on_score = (parsed > 0).then_some(parsed);
+                }
+            }
+        }
+        "UCI_ShowCurrLine" => {
+            if let Some(v) = value {
+                SHOW_CURRLINE.store(v.eq_ignore_ascii_case("true"), Ordering::Relaxed);
+            }
+        }
+        "UCI_ShowWDL" => {
+            if let Some(v) = value {
+                SHOW_WDL.store(v.eq_ignore_ascii_case("true"), Ordering::Relaxed);
+            }
+        }
         "Contempt" => {
             if let Some(v) = value {
                 if let Ok(parsed) = v.parse::<i32>() {
@@ -358,6 +840,43 @@ fn setoption(
                 }
             }
         }
+        "ContemptPerspective" => {
+            if let Some(v) = value {
+                let white = v.eq_ignore_ascii_case("white");
+                params.set("contempt_white_perspective", i32::from(white));
+            }
+        }
+        "DrawScore" => {
+            if let Some(v) = value {
+                if let Ok(parsed) = v.parse::<i32>() {
+                    params.set("draw_score", parsed.clamp(0, 1000));
+                }
+            }
+        }
+        "ScoreSmoothing" => {
+            // weight of the previous displayed score, in thousandths
+            if let Some(v) = value {
+                if let Ok(parsed) = v.parse::<i32>() {
+                    params.set("score_smoothing", parsed.clamp(0, 990));
+                }
+            }
+        }
+        "PolicyTemp" => {
+            // root policy temperature, in thousandths
+            if let Some(v) = value {
+                if let Ok(parsed) = v.parse::<i32>() {
+                    params.set("root_policy_temperature", parsed.clamp(100, 10000));
+                }
+            }
+        }
+        "MoveSelectTemp" => {
+            // temperature for sampling the played move by visits, in thousandths
+            if let Some(v) = value {
+                if let Ok(parsed) = v.parse::<i32>() {
+                    params.set("move_select_temperature", parsed.clamp(0, 10000));
+                }
+            }
+        }
         "UCI_Opponent" => {
             if contempt_override.is_some() || uci_rating_adv.is_some() {
                 return;
@@ -395,6 +914,46 @@ fn setoption(
     }
 }
 
+/// Threads used to clear the hash table when `ClearThreads` is 0, as
+/// clearing is memory bound and should not wait on the search setting.
+fn available_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from)
+}
+
+/// Maps the network at `path` into `slot`, or empties `slot` to go back to
+/// the built-in network when no path is given. On failure the network in
+/// use is kept. Returns whether the network in use changed.
+fn load_network<T>(slot: &mut Option<MappedWeights<T>>, path: Option<&str>, kind: &str) -> bool {
+    match path {
+        None | Some("") | Some("<empty>") => {
+            println!("info string using built-in {kind} network");
+            slot.take().is_some()
+        }
+        Some(path) => {
+            // SAFETY: the network structs are valid for any bit pattern,
+            // and the file size is checked against the struct size.
+            // Files named by their hash are checked against it, as at startup
+            let loaded = if network_name_hash(path).is_some() {
+                unsafe { try_read_into_struct_verified(path) }
+            } else {
+                unsafe { try_read_into_struct(path) }
+            };
+
+            match loaded {
+                Ok(weights) => {
+                    println!("info string loaded {kind} network from {path}");
+                    *slot = Some(weights);
+                    true
+                }
+                Err(err) => {
+                    println!("info string failed to load {kind} network from {path}: {err}");
+                    false
+                }
+            }
+        }
+    }
+}
+
 fn parse_name_value(commands: &[&str]) -> Option<(String, Option<String>)> {
     if commands.len() < 3 || commands[1] != "name" {
         return None;
@@ -493,17 +1052,23 @@ fn position(commands: Vec<&str>, pos: &mut ChessState) {
 fn go(
     commands: &[&str],
     tree: &mut Tree,
+    eval_cache: &EvalCache,
     pos: &ChessState,
     root_game_ply: u32,
     params: &MctsParams,
     report_moves: bool,
+    debug: bool,
     multipv: usize,
     policy: &PolicyNetwork,
     value: &ValueNetwork,
     threads: usize,
+    clear_threads: usize,
     move_overhead: usize,
     gui_compatibility: bool,
     disable_tree_reuse: bool,
+    stop_on_score: Option<i32>,
+    analysis_noise: Option<(f32, f32)>,
+    mate_search: bool,
     stored_message: &mut Option<String>,
     #[cfg(feature = "datagen")] temp: f32,
 ) {
@@ -515,6 +1080,9 @@ fn go(
     let mut incs = [None; 2];
     let mut movestogo = None;
     let mut opt_time = None;
+    let mut kld_min_gain = None;
+    let mut ponder = false;
+    let mut mate = None;
 
     let mut mode = "";
 
@@ -530,6 +1098,9 @@ fn go(
             "winc" => mode = "winc",
             "binc" => mode = "binc",
             "movestogo" => mode = "movestogo",
+            "kldgain" => mode = "kldgain",
+            "mate" => mode = "mate",
+            "ponder" => ponder = true,
             _ => match mode {
                 "nodes" => max_nodes = cmd.parse().unwrap_or(max_nodes),
                 "movetime" => max_time = cmd.parse().ok(),
@@ -539,18 +1110,24 @@ fn go(
                 "winc" => incs[0] = saturating_parse(cmd),
                 "binc" => incs[1] = saturating_parse(cmd),
                 "movestogo" => movestogo = saturating_parse(cmd),
+                "kldgain" => kld_min_gain = cmd.parse().ok(),
+                "mate" => mate = cmd.parse().ok(),
                 _ => mode = "none",
             },
         }
     }
 
     // `go wtime <wtime> btime <btime> winc <winc> binc <binc>``
-    if let Some(mut remaining) = times[pos.stm()] {
-        // apply move overhead
-        remaining = remaining.saturating_sub(move_overhead as u64).max(10);
-
-        let timeman =
-            SearchHelpers::get_time(remaining, incs[pos.stm()], root_game_ply, movestogo, params);
+    if let Some(remaining) = times[pos.stm()] {
+        let timeman = SearchHelpers::get_time(
+            remaining,
+            incs[pos.stm()],
+            root_game_ply,
+            pos.phase(),
+            movestogo,
+            move_overhead as u64,
+            params,
+        );
 
         opt_time = Some(timeman.0);
         max_time = Some(timeman.1);
@@ -563,9 +1140,12 @@ fn go(
     }
 
     let abort = AtomicBool::new(false);
+    let ponder = AtomicBool::new(ponder);
 
-    if disable_tree_reuse {
-        tree.clear(threads);
+    // resuming analysis of the same position keeps the tree, as its
+    // values were searched from the same root perspective
+    if disable_tree_reuse && tree.root_position().hash() != pos.hash() {
+        tree.clear(clear_threads);
     }
 
     tree.set_root_position(pos);
@@ -575,14 +1155,18 @@ fn go(
         opt_time,
         max_depth,
         max_nodes,
-        #[cfg(feature = "datagen")]
-        kld_min_gain: None,
+        kld_min_gain,
+        stop_score: stop_on_score,
+        mate,
     };
 
     std::thread::scope(|s| {
         s.spawn(|| {
-            let searcher = Searcher::new(tree, params, policy, value, &abort);
-            let mov = searcher
+            let searcher = Searcher::new(tree, params, policy, value, &abort)
+                .with_ponder(&ponder)
+                .with_eval_cache(eval_cache)
+                .with_mate_search(mate_search || mate.is_some());
+            let best = searcher
                 .search(
                     threads,
                     limits,
@@ -590,20 +1174,54 @@ fn go(
                     multipv,
                     gui_compatibility,
                     &mut 0,
-                    #[cfg(feature = "datagen")]
-                    false,
+                    analysis_noise,
                     #[cfg(feature = "datagen")]
                     temp,
                 )
                 .0;
-            println!("bestmove {}", pos.conv_mov_to_str(mov));
+
+            let select_temp = params.move_select_temperature();
+            let mov = if select_temp > 0.0 {
+                searcher.select_move_with_temperature(select_temp, &mut rand::random::<f32>)
+            } else {
+                best
+            };
+
+            // bestmove may not be sent while pondering, even if the search ended
+            while ponder.load(Ordering::Relaxed) && !abort.load(Ordering::Relaxed) {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+
+            if debug {
+                let root = &tree[tree.root_node()];
+                println!(
+                    "info string root gini {:.3} entropy {:.3}",
+                    root.gini_impurity(),
+                    tree.policy_entropy(tree.root_node())
+                );
+                println!("info string eval cache hits {}", eval_cache.hits());
+                searcher.display_tree_shape();
+            }
+
+            // the expected reply is only known for the best move
+            if let Some(ponder_move) = searcher.ponder_move().filter(|_| mov == best) {
+                let mut child = pos.clone();
+                child.make_move(mov);
+                println!(
+                    "bestmove {} ponder {}",
+                    pos.conv_mov_to_str(mov),
+                    child.conv_mov_to_str(ponder_move)
+                );
+            } else {
+                println!("bestmove {}", pos.conv_mov_to_str(mov));
+            }
 
             if report_moves {
                 searcher.display_moves();
             }
         });
 
-        *stored_message = handle_search_input(&abort);
+        *stored_message = handle_search_input(&abort, &ponder);
     });
 }
 
@@ -611,7 +1229,15 @@ fn run_perft(commands: &[&str], pos: &ChessState) {
     let depth = commands[1].parse().unwrap();
     let root_pos = pos.clone();
     let now = Instant::now();
-    let count = root_pos.perft(depth);
+
+    // `perft <depth> [threads] [hash mb]` runs the parallel, hashed version
+    let count = if let Some(threads) = commands.get(2).and_then(|t| t.parse().ok()) {
+        let hash_mb = commands.get(3).and_then(|h| h.parse().ok()).unwrap_or(0);
+        root_pos.perft_parallel(depth, threads, hash_mb)
+    } else {
+        root_pos.perft(depth)
+    };
+
     let time = now.elapsed().as_micros();
     println!(
         "perft {depth} time {} nodes {count} ({:.2} Mnps)",
@@ -620,7 +1246,53 @@ fn run_perft(commands: &[&str], pos: &ChessState) {
     );
 }
 
-fn handle_search_input(abort: &AtomicBool) -> Option<String> {
+/// Prints the statistics that PUCT selection sees for each root child
+/// of the last search, most visited first.
+fn display_root_nodes(tree: &Tree, params: &MctsParams) {
+    let _ = write_root_nodes(&mut io::stdout().lock(), tree, params);
+}
+
+fn write_root_nodes(out: &mut impl Write, tree: &Tree, params: &MctsParams) -> io::Result<()> {
+    let root = &tree[tree.root_node()];
+
+    if tree.is_empty() || root.num_actions() == 0 {
+        return writeln!(out, "info string no search tree");
+    }
+
+    let cpuct = SearchHelpers::get_cpuct(params, root, true, root.visits());
+    let fpu = SearchHelpers::get_fpu(params, root);
+    let expl = cpuct * SearchHelpers::get_explore_scaling(params, root);
+
+    let first_child_ptr = root.actions();
+    let mut children = (0..root.num_actions())
+        .map(|action| &tree[first_child_ptr + action])
+        .collect::<Vec<_>>();
+
+    children.sort_by_key(|child| std::cmp::Reverse(child.visits()));
+
+    let mut total = 0;
+
+    for child in children {
+        let q = SearchHelpers::get_action_value(child, fpu);
+        let u = expl * child.policy() / (1 + child.visits()) as f32;
+        total += child.visits();
+
+        writeln!(
+            out,
+            "{:<6} P {:>6.2}% V {:>10} Q {:>6.2}% PUCT {:.4} S({})",
+            tree.root_position().conv_mov_to_str(child.parent_move()),
+            child.policy() * 100.0,
+            child.visits(),
+            q * 100.0,
+            q + u,
+            child.state()
+        )?;
+    }
+
+    writeln!(out, "children visits {total} root visits {}", root.visits())
+}
+
+fn handle_search_input(abort: &AtomicBool, ponder: &AtomicBool) -> Option<String> {
     loop {
         let mut input = String::new();
         let bytes_read = io::stdin().read_line(&mut input).unwrap();
@@ -636,7 +1308,225 @@ fn handle_search_input(abort: &AtomicBool) -> Option<String> {
                 abort.store(true, Ordering::Relaxed);
                 return None;
             }
+            "ponderhit" => ponder.store(false, Ordering::Relaxed),
             _ => return Some(input),
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks;
+
+    use std::{
+        io::{BufReader, Lines},
+        process::{ChildStdout, Command, Stdio},
+        sync::mpsc::{self, Receiver},
+        thread,
+        time::Duration,
+    };
+
+    /// Set for the child process started by `uci_session_is_protocol_conformant`,
+    /// which runs the UCI loop on its stdin with zeroed networks.
+    const DRIVER_ENV: &str = "MONTY_UCI_TEST_DRIVER";
+
+    fn node_limits(nodes: usize) -> Limits {
+        Limits {
+            max_time: None,
+            opt_time: None,
+            max_depth: 256,
+            max_nodes: nodes,
+            kld_min_gain: None,
+            stop_score: None,
+            mate: None,
+        }
+    }
+
+    fn searched_tree(fen: &str, nodes: usize) -> Tree {
+        let mut tree = Tree::new_nodes(1 << 16, 1 << 12, 1);
+        tree.set_root_position(&ChessState::from_fen(fen));
+
+        let params = MctsParams::default();
+        let (policy, value) = networks::zeroed();
+        let abort = AtomicBool::new(false);
+
+        {
+            let searcher = Searcher::new(&tree, &params, policy, value, &abort);
+            let mut count = 0;
+
+            #[cfg(not(feature = "datagen"))]
+            searcher.search(1, node_limits(nodes), false, 1, false, &mut count, None);
+
+            #[cfg(feature = "datagen")]
+            searcher.search(
+                1,
+                node_limits(nodes),
+                false,
+                1,
+                false,
+                &mut count,
+                None,
+                0.0,
+            );
+        }
+
+        tree
+    }
+
+    #[test]
+    fn root_node_visits_sum_to_root_visits_minus_one() {
+        let tree = searched_tree(ChessState::STARTPOS, 300);
+
+        let mut out = Vec::new();
+        write_root_nodes(&mut out, &tree, &MctsParams::default()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let lines: Vec<&str> = out.lines().collect();
+        let (summary, children) = lines.split_last().unwrap();
+        assert_eq!(children.len(), 20);
+
+        let visits: Vec<u64> = children
+            .iter()
+            .map(|line| {
+                let mut tokens = line.split_whitespace();
+                tokens.find(|&token| token == "V");
+                tokens.next().unwrap().parse().unwrap()
+            })
+            .collect();
+
+        // most visited first
+        assert!(visits.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        let total: u64 = visits.iter().sum();
+        let root_visits = tree[tree.root_node()].visits();
+        assert_eq!(total, root_visits - 1);
+        assert_eq!(
+            *summary,
+            format!("children visits {total} root visits {root_visits}")
+        );
+    }
+
+    #[test]
+    fn root_nodes_without_a_search() {
+        let mut tree = Tree::new_nodes(1 << 12, 1 << 8, 1);
+        tree.set_root_position(&ChessState::default());
+
+        let mut out = Vec::new();
+        write_root_nodes(&mut out, &tree, &MctsParams::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "info string no search tree\n"
+        );
+    }
+
+    #[test]
+    #[ignore = "run as a child process by uci_session_is_protocol_conformant"]
+    fn uci_driver() {
+        if std::env::var_os(DRIVER_ENV).is_none() {
+            return;
+        }
+
+        let (policy, value) = networks::zeroed();
+        run(policy, value, false);
+    }
+
+    fn forward_lines(lines: Lines<BufReader<ChildStdout>>) -> Receiver<String> {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in lines.map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
+
+    /// Lines up to and including the first one `done` accepts.
+    fn read_until(rx: &Receiver<String>, done: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        loop {
+            let line = rx
+                .recv_timeout(Duration::from_secs(60))
+                .unwrap_or_else(|_| panic!("no reply from the engine after {lines:?}"));
+            let finished = done(&line);
+            lines.push(line);
+
+            if finished {
+                return lines;
+            }
+        }
+    }
+
+    #[test]
+    fn uci_session_is_protocol_conformant() {
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "uci::tests::uci_driver",
+                "--exact",
+                "--ignored",
+                "--nocapture",
+                "--quiet",
+            ])
+            .env(DRIVER_ENV, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let mut stdin = child.stdin.take().unwrap();
+        let rx = forward_lines(BufReader::new(child.stdout.take().unwrap()).lines());
+        let mut send = |cmd: &str| writeln!(stdin, "{cmd}").unwrap();
+
+        send("uci");
+        let preamble = read_until(&rx, |line| line == "uciok");
+        let preamble: Vec<&String> = preamble
+            .iter()
+            .skip_while(|line| !line.starts_with("id name "))
+            .collect();
+
+        assert!(preamble[0].starts_with("id name "), "{preamble:?}");
+        assert!(preamble[1].starts_with("id author "), "{preamble:?}");
+
+        let options = &preamble[2..preamble.len() - 1];
+        assert!(!options.is_empty());
+        for option in options {
+            assert!(
+                option.starts_with("option name ") && option.contains(" type "),
+                "{option}"
+            );
+        }
+
+        send("isready");
+        read_until(&rx, |line| line == "readyok");
+
+        send("ucinewgame");
+        send("position startpos moves e2e4");
+        send("go nodes 1000");
+
+        let search = read_until(&rx, |line| line.starts_with("bestmove "));
+        assert!(search.iter().any(|line| line.starts_with("info depth ")));
+
+        let bestmove = search.last().unwrap();
+        let mov = bestmove.split_whitespace().nth(1).unwrap();
+
+        let mut pos = ChessState::default();
+        position(vec!["position", "startpos", "moves", "e2e4"], &mut pos);
+
+        let mut legal = Vec::new();
+        pos.map_legal_moves(|mov| legal.push(pos.conv_mov_to_str(mov)));
+        assert!(legal.iter().any(|legal| legal == mov), "{bestmove}");
+
+        send("stop");
+        send("isready");
+        read_until(&rx, |line| line == "readyok");
+
+        send("quit");
+        assert!(child.wait().unwrap().success());
+    }
+}